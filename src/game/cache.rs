@@ -0,0 +1,99 @@
+use std::{collections::BTreeMap, mem::size_of_val};
+
+use bevy::{prelude::*, utils::HashMap};
+
+use super::{chunk::Chunk, gen::GeneratedChunk};
+
+const DEFAULT_CACHE_BUDGET: usize = 256 * 1024 * 1024;
+
+// How many bytes of unloaded chunks to keep in memory
+#[derive(Clone, Copy, Deref)]
+pub struct ChunkCacheBudget(pub usize);
+
+impl Default for ChunkCacheBudget {
+    fn default() -> Self {
+        Self(DEFAULT_CACHE_BUDGET)
+    }
+}
+
+struct CachedChunk {
+    generated: GeneratedChunk,
+    size: usize,
+    last_used: u64,
+}
+
+pub struct ChunkCache {
+    budget: usize,
+    size: usize,
+    clock: u64,
+    chunks: HashMap<IVec3, CachedChunk>,
+    recency: BTreeMap<u64, IVec3>,
+}
+
+fn cached_size(generated: &GeneratedChunk) -> usize {
+    size_of_val(generated.chunk.voxes())
+        + generated
+            .spill
+            .values()
+            .map(|writes| size_of_val(writes.as_slice()))
+            .sum::<usize>()
+}
+
+impl ChunkCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            size: 0,
+            clock: 0,
+            chunks: default(),
+            recency: default(),
+        }
+    }
+
+    // Returns the chunks evicted to stay within the budget, least recently used first
+    pub fn insert(
+        &mut self,
+        pos: IVec3,
+        generated: GeneratedChunk,
+    ) -> Vec<(IVec3, GeneratedChunk)> {
+        self.remove(pos);
+
+        self.clock += 1;
+        let size = cached_size(&generated);
+        self.size += size;
+        self.recency.insert(self.clock, pos);
+        self.chunks.insert(
+            pos,
+            CachedChunk {
+                generated,
+                size,
+                last_used: self.clock,
+            },
+        );
+
+        let mut evicted = Vec::default();
+        while self.size > self.budget {
+            let (_, pos) = self.recency.pop_first().unwrap();
+            evicted.push((pos, self.remove(pos).unwrap()));
+        }
+
+        evicted
+    }
+
+    pub fn remove(&mut self, pos: IVec3) -> Option<GeneratedChunk> {
+        let cached = self.chunks.remove(&pos)?;
+        self.recency.remove(&cached.last_used);
+        self.size -= cached.size;
+        Some(cached.generated)
+    }
+
+    pub fn get(&self, pos: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&pos).map(|cached| &cached.generated.chunk)
+    }
+
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = (IVec3, &mut Chunk)> {
+        self.chunks
+            .iter_mut()
+            .map(|(pos, cached)| (*pos, &mut cached.generated.chunk))
+    }
+}
@@ -4,7 +4,10 @@ use bevy::{input::mouse::MouseMotion, prelude::*, render::camera::Camera3d};
 
 use crate::state::GameState;
 
-use super::player::ChunkPos;
+use super::{
+    player::ChunkPos,
+    save::{CurrentWorld, PlayerMeta},
+};
 
 pub struct CamPlugin;
 
@@ -15,7 +18,8 @@ impl Plugin for CamPlugin {
                 SystemSet::on_update(GameState::Game)
                     .with_system(toggle_cursor)
                     .with_system(look_cam)
-                    .with_system(move_cam),
+                    .with_system(move_cam)
+                    .with_system(track_player),
             )
             .add_system_set(SystemSet::on_exit(GameState::Game).with_system(exit_cam));
     }
@@ -27,10 +31,39 @@ struct Rotation {
     yaw: f32,
 }
 
-fn init_cam(mut commands: Commands, mut windows: ResMut<Windows>) {
+impl Rotation {
+    fn quat(&self) -> Quat {
+        Quat::from_axis_angle(Vec3::Y, self.yaw) * Quat::from_axis_angle(Vec3::X, self.pitch)
+    }
+}
+
+fn init_cam(
+    mut commands: Commands,
+    world: Option<Res<CurrentWorld>>,
+    mut windows: ResMut<Windows>,
+) {
+    let (translation, rotation) = world
+        .and_then(|world| world.meta.player.clone())
+        .map_or_else(default, |player| {
+            (
+                player.position,
+                Rotation {
+                    pitch: player.pitch,
+                    yaw: player.yaw,
+                },
+            )
+        });
+
     commands
-        .spawn_bundle(PerspectiveCameraBundle::default())
-        .insert(Rotation::default())
+        .spawn_bundle(PerspectiveCameraBundle {
+            transform: Transform {
+                translation,
+                rotation: rotation.quat(),
+                ..default()
+            },
+            ..default()
+        })
+        .insert(rotation)
         .insert(ChunkPos::default());
 
     let window = windows.primary_mut();
@@ -61,8 +94,7 @@ fn look_cam(
                 .clamp(-FRAC_PI_2, FRAC_PI_2);
             rotation.yaw -= mouse_motion.delta.x * window_scale * MOUSE_SENSITIVITY;
 
-            tf.rotation = Quat::from_axis_angle(Vec3::Y, rotation.yaw)
-                * Quat::from_axis_angle(Vec3::X, rotation.pitch);
+            tf.rotation = rotation.quat();
         }
     }
 }
@@ -91,7 +123,28 @@ fn move_cam(
     }
 }
 
-fn exit_cam(mut windows: ResMut<Windows>) {
+fn track_player(
+    cams: Query<(&Transform, &Rotation), With<Camera3d>>,
+    world: Option<ResMut<CurrentWorld>>,
+) {
+    if let (Some(mut world), Ok((tf, rotation))) = (world, cams.get_single()) {
+        world.meta.player = Some(PlayerMeta {
+            position: tf.translation,
+            pitch: rotation.pitch,
+            yaw: rotation.yaw,
+        });
+    }
+}
+
+fn exit_cam(
+    mut commands: Commands,
+    cams: Query<Entity, With<Camera3d>>,
+    mut windows: ResMut<Windows>,
+) {
+    for cam_e in cams.iter() {
+        commands.entity(cam_e).despawn();
+    }
+
     let window = windows.primary_mut();
     window.set_cursor_lock_mode(false);
     window.set_cursor_visibility(true);
@@ -1,11 +1,14 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, mem::take};
 
-use bevy::{math::const_ivec3, prelude::*, tasks::Task};
-use futures_lite::future::{block_on, poll_once};
+use bevy::{math::const_ivec3, prelude::*, tasks::AsyncComputeTaskPool};
 
 use crate::state::GameState;
 
-use super::{map::RENDER_RADIUS_F32, render::RenderChunk, vox::Vox};
+use super::{
+    map::{Map, RENDER_RADIUS_F32},
+    render::RenderChunk,
+    vox::{Material, Vox},
+};
 
 pub const CHUNK_SIZE: usize = 32;
 pub const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
@@ -15,17 +18,23 @@ pub struct ChunkPlugin;
 
 impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_update(GameState::Game).with_system(resolve_chunks));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Game)
+                .with_system(resolve_chunks)
+                .with_system(advance_chunks),
+        );
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Default)]
 pub struct Chunk {
     voxes: Vec<Option<Vox>>,
     dirty: bool,
+    modified: bool,
+    unsaved: bool,
 }
 
-static ADJACENTS: &[IVec3] = &[
+pub static ADJACENTS: &[IVec3] = &[
     const_ivec3!([1, 0, 0]),
     const_ivec3!([-1, 0, 0]),
     const_ivec3!([0, 1, 0]),
@@ -34,8 +43,28 @@ static ADJACENTS: &[IVec3] = &[
     const_ivec3!([0, 0, -1]),
 ];
 
+pub type Face = Vec<Option<Material>>;
+
+const SHADOW_LIGHT: f32 = 0.55;
+
+fn in_bounds(pos: IVec3) -> bool {
+    pos.cmpge(IVec3::ZERO).all() && pos.cmplt(IVec3::splat(CHUNK_SIZE as i32)).all()
+}
+
+fn face_index(pos: IVec3, dir: IVec3) -> usize {
+    let (u, v) = if dir.x != 0 {
+        (pos.y, pos.z)
+    } else if dir.y != 0 {
+        (pos.x, pos.z)
+    } else {
+        (pos.x, pos.y)
+    };
+
+    (u + v * CHUNK_SIZE as i32) as usize
+}
+
 impl Chunk {
-    fn flatten(pos: IVec3) -> usize {
+    pub fn flatten(pos: IVec3) -> usize {
         pos.x as usize + pos.y as usize * CHUNK_SIZE + pos.z as usize * CHUNK_AREA
     }
 
@@ -47,44 +76,168 @@ impl Chunk {
         )
     }
 
-    pub fn generate(pos: IVec3) -> Self {
-        let mut voxes = vec![None; CHUNK_VOLUME];
-        for x in 0..CHUNK_SIZE {
-            for y in 0..CHUNK_SIZE {
-                for z in 0..CHUNK_SIZE {
-                    if ((x % 30) as f32 / 30. * CHUNK_SIZE as f32)
-                        > ((pos.y * CHUNK_SIZE as i32) + y as i32) as f32
-                    {
-                        voxes[Self::flatten(IVec3::new(x as i32, y as i32, z as i32))] =
-                            Some(Vox {
-                                color: Color::rgb(
-                                    (x % 100) as f32 / 100.,
-                                    (y % 10) as f32 / 10.,
-                                    (z % 55) as f32 / 55.,
-                                ),
-                                visible: true,
-                            });
-                    }
+    pub fn new(voxes: Vec<Option<Vox>>) -> Self {
+        Self {
+            voxes,
+            dirty: true,
+            modified: false,
+            unsaved: false,
+        }
+    }
+
+    pub fn loaded(voxes: Vec<Option<Vox>>) -> Self {
+        Self {
+            modified: true,
+            ..Self::new(voxes)
+        }
+    }
+
+    pub fn voxes(&self) -> &[Option<Vox>] {
+        &self.voxes
+    }
+
+    // Whether the chunk differs from the generator's output
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    // Whether the chunk was edited since it was last queued for saving, clearing the flag
+    pub fn take_unsaved(&mut self) -> bool {
+        take(&mut self.unsaved)
+    }
+
+    pub fn get(&self, pos: IVec3) -> Option<&Vox> {
+        self.voxes[Self::flatten(pos)].as_ref()
+    }
+
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        self.get(pos).is_some_and(|vox| !vox.material.is_liquid())
+    }
+
+    // For generation, which the chunk can be regenerated with, so it doesn't need saving. Changes
+    // made while playing go through `edit`.
+    pub fn set(&mut self, pos: IVec3, vox: Option<Vox>) {
+        self.voxes[Self::flatten(pos)] = vox;
+        self.dirty = true;
+    }
+
+    pub fn fill_empty(&mut self, writes: &[(IVec3, Vox)]) -> bool {
+        let mut changed = false;
+        for (pos, vox) in writes {
+            let i = Self::flatten(*pos);
+            if self.voxes[i].is_none() {
+                self.voxes[i] = Some(vox.clone());
+                changed = true;
+            }
+        }
+
+        self.dirty |= changed;
+        changed
+    }
+
+    // Unlike `fill_empty`, overwrites whatever is there, so the chunk needs saving afterwards
+    pub fn edit(&mut self, writes: &[(IVec3, Vox)]) {
+        for (pos, vox) in writes {
+            self.voxes[Self::flatten(*pos)] = Some(vox.clone());
+        }
+
+        self.dirty = true;
+        self.modified = true;
+        self.unsaved = true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxes.iter().all(Option::is_none)
+    }
+
+    // FNV-1a over each vox's color quantized to bytes and material, stable across runs and
+    // platforms even if float math rounds slightly differently
+    #[cfg(test)]
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = super::codec::Fnv::default();
+        for vox in &self.voxes {
+            match vox {
+                Some(vox) => {
+                    hash.write(&[1]);
+                    hash.write(
+                        &vox.color
+                            .as_rgba_f32()
+                            .map(|channel| (channel.clamp(0., 1.) * u8::MAX as f32).round() as u8),
+                    );
+                    hash.write(&vox.material.0.to_le_bytes());
                 }
+                None => hash.write(&[0]),
             }
         }
 
-        for x in 1..CHUNK_SIZE as i32 - 1 {
-            for y in 1..CHUNK_SIZE as i32 - 1 {
-                for z in 1..CHUNK_SIZE as i32 - 1 {
-                    if ADJACENTS
-                        .iter()
-                        .all(|adj| voxes[Chunk::flatten(IVec3::new(x, y, z) + *adj)].is_some())
-                    {
-                        if let Some(vox) = &mut voxes[Chunk::flatten(IVec3::new(x, y, z))] {
-                            vox.visible = false;
-                        }
-                    }
+        hash.finish()
+    }
+
+    // Clears the columns of `sky` that sunlight can't get through this chunk in
+    pub fn block_sky(&self, sky: &mut [bool]) {
+        for (column, lit) in sky.iter_mut().enumerate() {
+            let (x, z) = ((column % CHUNK_SIZE) as i32, (column / CHUNK_SIZE) as i32);
+            *lit &= (0..CHUNK_SIZE as i32).all(|y| !self.is_solid(IVec3::new(x, y, z)));
+        }
+    }
+
+    // `sky` holds whether sunlight reaches each column from above. Returns whether it leaves
+    // through the bottom.
+    pub fn update_light(&mut self, sky: &[bool]) -> Vec<bool> {
+        let mut below = sky.to_vec();
+        for (column, lit) in below.iter_mut().enumerate() {
+            let (x, z) = ((column % CHUNK_SIZE) as i32, (column / CHUNK_SIZE) as i32);
+            for y in (0..CHUNK_SIZE as i32).rev() {
+                if let Some(vox) = &mut self.voxes[Self::flatten(IVec3::new(x, y, z))] {
+                    vox.light = if *lit { 1. } else { SHADOW_LIGHT };
+                    *lit &= vox.material.is_liquid();
                 }
             }
         }
 
-        Self { voxes, dirty: true }
+        below
+    }
+
+    // The layer of materials on the side of the chunk facing `dir`
+    pub fn face(&self, dir: IVec3) -> Face {
+        let mut face = vec![None; CHUNK_AREA];
+        for (i, vox) in self.voxes.iter().enumerate() {
+            let pos = Self::expand(i);
+            if !in_bounds(pos + dir) {
+                face[face_index(pos, dir)] = vox.as_ref().map(|vox| vox.material);
+            }
+        }
+
+        face
+    }
+
+    // `faces` holds the facing layer of the neighbor in each of `ADJACENTS`
+    pub fn update_visibility(&mut self, faces: &[Face]) {
+        for i in 0..CHUNK_VOLUME {
+            let pos = Self::expand(i);
+            let material = match &self.voxes[i] {
+                Some(vox) => vox.material,
+                None => continue,
+            };
+
+            let hidden = ADJACENTS.iter().zip(faces).all(|(adj, face)| {
+                let adj_material = if in_bounds(pos + *adj) {
+                    self.voxes[Self::flatten(pos + *adj)]
+                        .as_ref()
+                        .map(|vox| vox.material)
+                } else {
+                    face[face_index(pos, *adj)]
+                };
+
+                adj_material.is_some_and(|adj_material| adj_material.hides(material))
+            });
+
+            if let Some(vox) = &mut self.voxes[i] {
+                vox.visible = !hidden;
+            }
+        }
+
+        self.dirty = true;
     }
 
     pub fn extract(&mut self, commands: &mut Commands, chunk_e: Entity, pos: IVec3) {
@@ -103,19 +256,14 @@ const PI_4_3: f32 = 4. / 3. * PI;
 const GEN_LIMIT: usize =
     (PI_4_3 * RENDER_RADIUS_F32 * RENDER_RADIUS_F32 * RENDER_RADIUS_F32 * GEN_RATE_LIMIT) as usize;
 
-fn resolve_chunks(mut commands: Commands, mut loading_chunks: Query<(Entity, &mut Task<Chunk>)>) {
-    let mut gen_count = 0;
-    for (chunk_e, mut task) in loading_chunks.iter_mut() {
-        if let Some(chunk) = block_on(poll_once(&mut *task)) {
-            commands
-                .entity(chunk_e)
-                .insert(chunk)
-                .remove::<Task<Chunk>>();
-
-            gen_count += 1;
-            if gen_count >= GEN_LIMIT {
-                break;
-            }
-        }
-    }
+fn resolve_chunks(mut chunks: Query<&mut Chunk>, mut map: ResMut<Map>) {
+    map.resolve_chunks(&mut chunks);
+}
+
+fn advance_chunks(
+    mut chunks: Query<&mut Chunk>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    mut map: ResMut<Map>,
+) {
+    map.advance_chunks(&mut chunks, &thread_pool, GEN_LIMIT);
 }
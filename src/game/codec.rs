@@ -0,0 +1,415 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use super::vox::{Material, Vox};
+
+// Layout after the version and flags bytes, deflated if `DEFLATE_FLAG` is set:
+// vox count, palette length and palette entries (air is always index 0 and isn't stored),
+// run count, each run's palette index packed into the fewest bits the palette needs, then each
+// run's length. Counts and lengths are LEB128 varints.
+pub const CODEC_VERSION: u8 = 1;
+const DEFLATE_FLAG: u8 = 1;
+const MAX_VOXES: u64 = 1 << 20;
+const MAX_BODY_LEN: u64 = 64 << 20;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x100_0000_01b3;
+
+// FNV-1a, which unlike the standard library's hashers stays the same across runs and platforms,
+// so its hashes can be stored
+pub struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(FNV_OFFSET)
+    }
+}
+
+impl Fnv {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn fnv(bytes: &[u8]) -> u64 {
+    let mut hash = Fnv::default();
+    hash.write(bytes);
+    hash.finish()
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    UnsupportedVersion(u8),
+    UnsupportedFlags(u8),
+    Truncated,
+    TooLarge,
+    InvalidIndex(u32),
+    LengthMismatch,
+    TrailingBytes,
+    Io(io::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported chunk codec version {}", version)
+            }
+            Self::UnsupportedFlags(flags) => {
+                write!(f, "unsupported chunk codec flags {:#x}", flags)
+            }
+            Self::Truncated => write!(f, "chunk data is truncated"),
+            Self::TooLarge => write!(f, "chunk data is too large"),
+            Self::InvalidIndex(index) => write!(f, "palette index {} is out of range", index),
+            Self::LengthMismatch => write!(f, "runs don't add up to the vox count"),
+            Self::TrailingBytes => write!(f, "chunk data has trailing bytes"),
+            Self::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for CodecError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+type PaletteKey = ([u32; 4], u16);
+
+fn palette_key(vox: &Vox) -> PaletteKey {
+    (vox.color.as_rgba_f32().map(f32::to_bits), vox.material.0)
+}
+
+fn index_bits(palette_len: usize) -> u32 {
+    usize::BITS - (palette_len.max(1) - 1).leading_zeros()
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        if len > self.bytes.len() {
+            return Err(CodecError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<u64, CodecError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(CodecError::TooLarge)
+    }
+
+    fn bounded_varint(&mut self, max: u64) -> Result<usize, CodecError> {
+        match self.varint()? {
+            value if value <= max => Ok(value as usize),
+            _ => Err(CodecError::TooLarge),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, CodecError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, CodecError> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+pub fn encode(voxes: &[Option<Vox>], deflate: bool) -> Vec<u8> {
+    let mut palette = Vec::<&Vox>::default();
+    let mut palette_indices = HashMap::<PaletteKey, u32>::default();
+    let mut runs = Vec::<(u32, u64)>::default();
+
+    for vox in voxes {
+        let index = match vox {
+            Some(vox) => *palette_indices.entry(palette_key(vox)).or_insert_with(|| {
+                palette.push(vox);
+                palette.len() as u32
+            }),
+            None => 0,
+        };
+
+        match runs.last_mut() {
+            Some((run_index, len)) if *run_index == index => *len += 1,
+            _ => runs.push((index, 1)),
+        }
+    }
+
+    let mut body = Vec::default();
+    write_varint(&mut body, voxes.len() as u64);
+    write_varint(&mut body, palette.len() as u64);
+    for vox in palette.iter() {
+        for channel in vox.color.as_rgba_f32() {
+            body.extend(channel.to_le_bytes());
+        }
+        body.extend(vox.material.0.to_le_bytes());
+    }
+
+    write_varint(&mut body, runs.len() as u64);
+    let bits = index_bits(palette.len() + 1);
+    let (mut packed, mut packed_bits) = (0u64, 0);
+    for (index, _) in &runs {
+        packed |= (*index as u64) << packed_bits;
+        packed_bits += bits;
+        while packed_bits >= 8 {
+            body.push(packed as u8);
+            packed >>= 8;
+            packed_bits -= 8;
+        }
+    }
+    if packed_bits > 0 {
+        body.push(packed as u8);
+    }
+
+    for (_, len) in &runs {
+        write_varint(&mut body, *len);
+    }
+
+    if deflate {
+        let mut encoder =
+            DeflateEncoder::new(vec![CODEC_VERSION, DEFLATE_FLAG], Compression::default());
+        encoder.write_all(&body).unwrap();
+        encoder.finish().unwrap()
+    } else {
+        [vec![CODEC_VERSION, 0], body].concat()
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Vec<Option<Vox>>, CodecError> {
+    let mut reader = Reader { bytes };
+    let header = reader.take(2)?;
+    let (version, flags) = (header[0], header[1]);
+    if version != CODEC_VERSION {
+        return Err(CodecError::UnsupportedVersion(version));
+    }
+
+    let inflated;
+    let mut reader = match flags {
+        0 => reader,
+        DEFLATE_FLAG => {
+            let mut body = Vec::default();
+            DeflateDecoder::new(reader.bytes)
+                .take(MAX_BODY_LEN + 1)
+                .read_to_end(&mut body)?;
+            if body.len() as u64 > MAX_BODY_LEN {
+                return Err(CodecError::TooLarge);
+            }
+
+            inflated = body;
+            Reader { bytes: &inflated }
+        }
+        _ => return Err(CodecError::UnsupportedFlags(flags)),
+    };
+
+    let len = reader.bounded_varint(MAX_VOXES)?;
+    let palette_len = reader.bounded_varint(len as u64)?;
+    let mut palette = vec![None];
+    for _ in 0..palette_len {
+        let color = Color::rgba(reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?);
+        palette.push(Some(Vox::new(color, Material(reader.u16()?))));
+    }
+
+    let run_count = reader.bounded_varint(len as u64)?;
+    let bits = index_bits(palette.len());
+    let packed = reader.take((run_count * bits as usize).div_ceil(8))?;
+    let mut indices = Vec::with_capacity(run_count);
+    let (mut bytes, mut unpacked, mut unpacked_bits) = (packed.iter(), 0u64, 0);
+    for _ in 0..run_count {
+        while unpacked_bits < bits {
+            unpacked |= (*bytes.next().unwrap() as u64) << unpacked_bits;
+            unpacked_bits += 8;
+        }
+
+        indices.push((unpacked & ((1 << bits) - 1)) as u32);
+        unpacked >>= bits;
+        unpacked_bits -= bits;
+    }
+
+    let mut voxes = Vec::with_capacity(len);
+    for index in indices {
+        let run_len = reader.bounded_varint((len - voxes.len()) as u64)?;
+        let vox = palette
+            .get(index as usize)
+            .ok_or(CodecError::InvalidIndex(index))?;
+        voxes.extend((0..run_len).map(|_| vox.clone()));
+    }
+
+    if voxes.len() != len {
+        return Err(CodecError::LengthMismatch);
+    }
+
+    if !reader.bytes.is_empty() {
+        return Err(CodecError::TrailingBytes);
+    }
+
+    Ok(voxes)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::game::{chunk::CHUNK_VOLUME, gen::WorldGenerators, stage::GenStage};
+
+    use super::*;
+
+    const FUZZ_ITERATIONS: usize = 2000;
+
+    fn keys(voxes: &[Option<Vox>]) -> Vec<Option<PaletteKey>> {
+        voxes
+            .iter()
+            .map(|vox| vox.as_ref().map(palette_key))
+            .collect()
+    }
+
+    fn assert_round_trip(voxes: &[Option<Vox>]) {
+        for deflate in [false, true] {
+            let decoded = decode(&encode(voxes, deflate)).unwrap();
+            assert_eq!(keys(&decoded), keys(voxes));
+        }
+    }
+
+    fn random_voxes(rng: &mut StdRng) -> Vec<Option<Vox>> {
+        let palette = (0..rng.gen_range(1..300))
+            .map(|_| {
+                if rng.gen_bool(0.8) {
+                    Some(Vox::new(
+                        Color::rgba(rng.gen(), rng.gen(), rng.gen(), rng.gen()),
+                        Material(rng.gen()),
+                    ))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut voxes = Vec::default();
+        let len = rng.gen_range(0..CHUNK_VOLUME);
+        while voxes.len() < len {
+            let vox = &palette[rng.gen_range(0..palette.len())];
+            let max_run = if rng.gen() { 1 } else { 64 };
+            let run = rng.gen_range(1..=max_run);
+            voxes.extend((0..run).map(|_| vox.clone()));
+        }
+
+        voxes
+    }
+
+    #[test]
+    fn round_trips_empty_and_full() {
+        assert_round_trip(&[]);
+        assert_round_trip(&vec![None; CHUNK_VOLUME]);
+        assert_round_trip(&vec![Some(Vox::solid(Color::RED)); CHUNK_VOLUME]);
+    }
+
+    #[test]
+    fn round_trips_generated_chunks() {
+        let generators = WorldGenerators::embedded();
+        for name in generators.names() {
+            let generator = generators.create(name, 0).unwrap();
+            for y in -2..=1 {
+                let chunk = generator
+                    .generate(IVec3::new(0, y, 0), GenStage::Terrain, default())
+                    .chunk;
+                assert_round_trip(chunk.voxes());
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_random_voxes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..50 {
+            assert_round_trip(&random_voxes(&mut rng));
+        }
+    }
+
+    #[test]
+    fn compresses_generated_chunks() {
+        let chunk = WorldGenerators::embedded()
+            .create("noise", 0)
+            .unwrap()
+            .generate(IVec3::new(0, -1, 0), GenStage::Terrain, default())
+            .chunk;
+
+        let raw = encode(chunk.voxes(), false);
+        let deflated = encode(chunk.voxes(), true);
+        assert!(raw.len() < CHUNK_VOLUME);
+        assert!(deflated.len() <= raw.len());
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let mut bytes = encode(&[None], false);
+        bytes[0] = CODEC_VERSION + 1;
+        assert!(matches!(
+            decode(&bytes),
+            Err(CodecError::UnsupportedVersion(version)) if version == CODEC_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn fuzz_random_bytes() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..FUZZ_ITERATIONS {
+            let mut bytes = (0..rng.gen_range(0..256))
+                .map(|_| rng.gen())
+                .collect::<Vec<u8>>();
+            if let Some(version) = bytes.first_mut() {
+                *version = CODEC_VERSION;
+            }
+
+            let _ = decode(&bytes);
+        }
+    }
+
+    #[test]
+    fn fuzz_mutated_encodings() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..FUZZ_ITERATIONS / 10 {
+            let voxes = random_voxes(&mut rng);
+            let mut bytes = encode(&voxes, rng.gen());
+            for _ in 0..rng.gen_range(1..4) {
+                let i = rng.gen_range(0..bytes.len());
+                bytes[i] ^= 1 << rng.gen_range(0..8);
+            }
+            bytes.truncate(rng.gen_range(0..=bytes.len()));
+
+            if let Ok(decoded) = decode(&bytes) {
+                assert!(decoded.len() as u64 <= MAX_VOXES);
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+
+use crate::game::{
+    chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME},
+    vox::Vox,
+};
+
+use super::WorldGenerator;
+
+pub struct EmptyGenerator;
+
+impl WorldGenerator for EmptyGenerator {
+    fn generate(&self, _: IVec3) -> Chunk {
+        Chunk::new(vec![None; CHUNK_VOLUME])
+    }
+}
+
+const FLAT_COLOR: Color = Color::rgb(0.3, 0.6, 0.2);
+
+pub struct FlatGenerator;
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, pos: IVec3) -> Chunk {
+        let mut voxes = vec![None; CHUNK_VOLUME];
+        if pos.y < 0 {
+            voxes.fill(Some(Vox::solid(FLAT_COLOR)));
+        }
+
+        Chunk::new(voxes)
+    }
+}
+
+pub struct SawtoothGenerator;
+
+impl WorldGenerator for SawtoothGenerator {
+    fn generate(&self, pos: IVec3) -> Chunk {
+        let mut voxes = vec![None; CHUNK_VOLUME];
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in 0..CHUNK_SIZE {
+                    if ((x % 30) as f32 / 30. * CHUNK_SIZE as f32)
+                        > ((pos.y * CHUNK_SIZE as i32) + y as i32) as f32
+                    {
+                        voxes[Chunk::flatten(IVec3::new(x as i32, y as i32, z as i32))] =
+                            Some(Vox::solid(Color::rgb(
+                                (x % 100) as f32 / 100.,
+                                (y % 10) as f32 / 10.,
+                                (z % 55) as f32 / 55.,
+                            )));
+                    }
+                }
+            }
+        }
+
+        Chunk::new(voxes)
+    }
+}
@@ -0,0 +1,146 @@
+use bevy::prelude::*;
+
+use crate::game::noise::Noise;
+
+const CLIMATE_SCALE: f32 = 1. / 1024.;
+const CLIMATE_OCTAVES: u32 = 3;
+const CLIMATE_CONTRAST: f32 = 2.;
+const BLEND_RADIUS: f32 = 0.3;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Biome {
+    Desert,
+    Grassland,
+    Forest,
+    Tundra,
+    Mountains,
+}
+
+pub struct BiomeProfile {
+    pub temperature: f32,
+    pub humidity: f32,
+    pub base_height: f32,
+    pub amplitude: f32,
+    pub surface: Color,
+    pub subsurface: Color,
+    pub subsurface_depth: i32,
+}
+
+const BIOMES: [Biome; 5] = [
+    Biome::Desert,
+    Biome::Grassland,
+    Biome::Forest,
+    Biome::Tundra,
+    Biome::Mountains,
+];
+
+const DESERT: BiomeProfile = BiomeProfile {
+    temperature: 0.6,
+    humidity: -0.6,
+    base_height: 6.,
+    amplitude: 12.,
+    surface: Color::rgb(0.86, 0.78, 0.5),
+    subsurface: Color::rgb(0.76, 0.64, 0.4),
+    subsurface_depth: 8,
+};
+
+const GRASSLAND: BiomeProfile = BiomeProfile {
+    temperature: 0.2,
+    humidity: 0.,
+    base_height: 4.,
+    amplitude: 20.,
+    surface: Color::rgb(0.3, 0.6, 0.2),
+    subsurface: Color::rgb(0.45, 0.3, 0.2),
+    subsurface_depth: 4,
+};
+
+const FOREST: BiomeProfile = BiomeProfile {
+    temperature: 0.2,
+    humidity: 0.6,
+    base_height: 8.,
+    amplitude: 28.,
+    surface: Color::rgb(0.2, 0.45, 0.15),
+    subsurface: Color::rgb(0.4, 0.27, 0.18),
+    subsurface_depth: 5,
+};
+
+const TUNDRA: BiomeProfile = BiomeProfile {
+    temperature: -0.6,
+    humidity: 0.3,
+    base_height: 2.,
+    amplitude: 12.,
+    surface: Color::rgb(0.92, 0.94, 0.96),
+    subsurface: Color::rgb(0.5, 0.45, 0.4),
+    subsurface_depth: 3,
+};
+
+const MOUNTAINS: BiomeProfile = BiomeProfile {
+    temperature: -0.3,
+    humidity: -0.5,
+    base_height: 40.,
+    amplitude: 96.,
+    surface: Color::rgb(0.5, 0.5, 0.52),
+    subsurface: Color::rgb(0.45, 0.45, 0.47),
+    subsurface_depth: 2,
+};
+
+impl Biome {
+    pub fn profile(self) -> &'static BiomeProfile {
+        match self {
+            Biome::Desert => &DESERT,
+            Biome::Grassland => &GRASSLAND,
+            Biome::Forest => &FOREST,
+            Biome::Tundra => &TUNDRA,
+            Biome::Mountains => &MOUNTAINS,
+        }
+    }
+}
+
+pub struct BiomeMap {
+    temperature: Noise,
+    humidity: Noise,
+}
+
+impl BiomeMap {
+    pub fn new(seed: u64) -> Self {
+        let noise = Noise::new(seed);
+        Self {
+            temperature: noise.with_offset(2),
+            humidity: noise.with_offset(3),
+        }
+    }
+
+    fn climate(&self, column: IVec2) -> Vec2 {
+        let pos = column.as_vec2() * CLIMATE_SCALE;
+        (Vec2::new(
+            self.temperature.fbm2(pos, CLIMATE_OCTAVES),
+            self.humidity.fbm2(pos, CLIMATE_OCTAVES),
+        ) * CLIMATE_CONTRAST)
+            .clamp(-Vec2::ONE, Vec2::ONE)
+    }
+
+    pub fn weights(&self, column: IVec2) -> [(Biome, f32); BIOMES.len()] {
+        let climate = self.climate(column);
+        let mut weights = BIOMES.map(|biome| {
+            let profile = biome.profile();
+            let distance =
+                climate.distance_squared(Vec2::new(profile.temperature, profile.humidity));
+            (biome, (-distance / (BLEND_RADIUS * BLEND_RADIUS)).exp())
+        });
+
+        let total = weights.iter().map(|(_, weight)| weight).sum::<f32>();
+        for (_, weight) in &mut weights {
+            *weight /= total;
+        }
+
+        weights
+    }
+
+    pub fn biome_at(&self, column: IVec2) -> Biome {
+        self.weights(column)
+            .into_iter()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap()
+            .0
+    }
+}
@@ -0,0 +1,132 @@
+use std::f32::consts::PI;
+
+use bevy::{math::const_vec3, prelude::*};
+
+use crate::game::{
+    chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME},
+    noise::{hash, Noise},
+};
+
+use super::GenPass;
+
+const CAVE_SCALE: Vec3 = const_vec3!([1. / 48., 1. / 24., 1. / 48.]);
+const CAVE_OCTAVES: u32 = 3;
+const CAVE_THRESHOLD: f32 = 0.3;
+
+const WORM_CELL_SIZE: i32 = 128;
+const WORMS_PER_CELL: u64 = 3;
+const WORM_STEPS: usize = 160;
+const WORM_TURN_RATE: f32 = 0.25;
+const WORM_MAX_PITCH: f32 = PI / 6.;
+const WORM_MIN_RADIUS: f32 = 1.5;
+const WORM_MAX_RADIUS: f32 = 4.5;
+
+pub struct Carver {
+    seed: u64,
+    caves: Noise,
+}
+
+impl Carver {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            caves: Noise::new(seed).with_offset(1),
+        }
+    }
+
+    fn carve_caves(&self, chunk_origin: IVec3, chunk: &mut Chunk) {
+        for i in 0..CHUNK_VOLUME {
+            let pos = Chunk::expand(i);
+            if chunk.is_solid(pos)
+                && self
+                    .caves
+                    .fbm3((chunk_origin + pos).as_vec3() * CAVE_SCALE, CAVE_OCTAVES)
+                    > CAVE_THRESHOLD
+            {
+                chunk.set(pos, None);
+            }
+        }
+    }
+
+    fn carve_worm(&self, cell: IVec3, worm: u64, chunk_origin: IVec3, chunk: &mut Chunk) {
+        let seed = hash(self.seed, cell).wrapping_add(worm);
+        let noise = Noise::new(seed);
+        let start = hash(seed, IVec3::ZERO);
+
+        let mut pos = (cell * WORM_CELL_SIZE).as_vec3()
+            + Vec3::new(
+                (start & 0xffff) as f32,
+                (start >> 16 & 0xffff) as f32,
+                (start >> 32 & 0xffff) as f32,
+            ) / 0x10000 as f32
+                * WORM_CELL_SIZE as f32;
+        let mut yaw = (start >> 48) as f32 / 0x10000 as f32 * 2. * PI;
+        let mut pitch = 0.;
+
+        let chunk_max = chunk_origin + IVec3::splat(CHUNK_SIZE as i32);
+        for step in 0..WORM_STEPS {
+            let t = step as f32 * 0.05;
+            yaw += noise.get2(Vec2::new(t, 0.)) * WORM_TURN_RATE;
+            pitch = (pitch + noise.get2(Vec2::new(t, 10.)) * WORM_TURN_RATE)
+                .clamp(-WORM_MAX_PITCH, WORM_MAX_PITCH);
+            let radius = WORM_MIN_RADIUS
+                + (noise.get2(Vec2::new(t, 20.)) + 1.) / 2. * (WORM_MAX_RADIUS - WORM_MIN_RADIUS);
+
+            pos += Vec3::new(
+                yaw.cos() * pitch.cos(),
+                pitch.sin(),
+                yaw.sin() * pitch.cos(),
+            );
+
+            let min = (pos - radius).floor().as_ivec3().max(chunk_origin);
+            let max = (pos + radius).ceil().as_ivec3().min(chunk_max);
+            if min.cmpge(max).any() {
+                continue;
+            }
+
+            for x in min.x..max.x {
+                for y in min.y..max.y {
+                    for z in min.z..max.z {
+                        let vox_pos = IVec3::new(x, y, z);
+                        let local_pos = vox_pos - chunk_origin;
+                        if (vox_pos.as_vec3() + 0.5).distance_squared(pos) < radius * radius
+                            && chunk.is_solid(local_pos)
+                        {
+                            chunk.set(local_pos, None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn carve_worms(&self, chunk_origin: IVec3, chunk: &mut Chunk) {
+        let reach = WORM_STEPS as i32 + WORM_MAX_RADIUS.ceil() as i32;
+        let min_cell = (chunk_origin - reach).as_vec3() / WORM_CELL_SIZE as f32;
+        let max_cell = (chunk_origin + CHUNK_SIZE as i32 + reach).as_vec3() / WORM_CELL_SIZE as f32;
+        let (min_cell, max_cell) = (min_cell.floor().as_ivec3(), max_cell.floor().as_ivec3());
+
+        for x in min_cell.x..=max_cell.x {
+            for y in min_cell.y..=max_cell.y {
+                for z in min_cell.z..=max_cell.z {
+                    let cell = IVec3::new(x, y, z);
+                    for worm in 0..hash(self.seed, cell) % (WORMS_PER_CELL + 1) {
+                        self.carve_worm(cell, worm, chunk_origin, chunk);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GenPass for Carver {
+    fn apply(&self, pos: IVec3, chunk: &mut Chunk) {
+        if chunk.is_empty() {
+            return;
+        }
+
+        let chunk_origin = pos * CHUNK_SIZE as i32;
+        self.carve_caves(chunk_origin, chunk);
+        self.carve_worms(chunk_origin, chunk);
+    }
+}
@@ -0,0 +1,48 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::game::{
+    chunk::{Chunk, CHUNK_SIZE},
+    vox::Vox,
+};
+
+pub type VoxWrites = Vec<(IVec3, Vox)>;
+pub type Spill = HashMap<IVec3, VoxWrites>;
+
+pub trait Feature: Send + Sync {
+    fn place(&self, pos: IVec3, chunk: &Chunk, writer: &mut FeatureWriter);
+}
+
+pub struct FeatureWriter {
+    pos: IVec3,
+    own: VoxWrites,
+    spill: Spill,
+}
+
+impl FeatureWriter {
+    pub fn new(pos: IVec3) -> Self {
+        Self {
+            pos,
+            own: VoxWrites::default(),
+            spill: Spill::default(),
+        }
+    }
+
+    pub fn place(&mut self, world_pos: IVec3, vox: Vox) {
+        let chunk_pos = (world_pos.as_vec3() / CHUNK_SIZE as f32).floor().as_ivec3();
+        let local_pos = world_pos - chunk_pos * CHUNK_SIZE as i32;
+
+        if chunk_pos == self.pos {
+            self.own.push((local_pos, vox));
+        } else {
+            self.spill
+                .entry(chunk_pos)
+                .or_default()
+                .push((local_pos, vox));
+        }
+    }
+
+    pub fn finish(self, chunk: &mut Chunk) -> Spill {
+        chunk.fill_empty(&self.own);
+        self.spill
+    }
+}
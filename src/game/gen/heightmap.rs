@@ -0,0 +1,288 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::game::{
+    chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME},
+    vox::Vox,
+};
+
+use super::WorldGenerator;
+
+const ASSETS_DIR: &str = "assets";
+const HEIGHTMAPS_DIR: &str = "assets/heightmaps";
+const SURFACE_DEPTH: i32 = 3;
+const SUBSURFACE_SHADE: f32 = 0.7;
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum HeightmapFill {
+    Empty,
+    Flat { height: i32, color: Color },
+}
+
+#[derive(Clone, Deserialize)]
+pub struct HeightmapSettings {
+    pub heightmap: String,
+    pub color_map: Option<String>,
+    pub horizontal_scale: f32,
+    pub vertical_scale: f32,
+    pub origin: IVec3,
+    pub color: Color,
+    pub fill: HeightmapFill,
+}
+
+impl HeightmapSettings {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|settings| ron::from_str(&settings).map_err(|err| err.to_string()))
+            .and_then(|settings: Self| {
+                settings.validate()?;
+                Ok(settings)
+            })
+    }
+
+    // Zero, negative or infinite scales would sample or stack the heightmap into broken terrain
+    fn validate(&self) -> Result<(), String> {
+        for (name, scale) in [
+            ("horizontal_scale", self.horizontal_scale),
+            ("vertical_scale", self.vertical_scale),
+        ] {
+            if !(scale > 0. && scale.is_finite()) {
+                return Err(format!("{} must be positive", name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_all() -> Vec<(String, Self)> {
+        let entries = match fs::read_dir(HEIGHTMAPS_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::default(),
+        };
+
+        let mut settings = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ron"))
+            .filter_map(|path| {
+                let name = path.file_stem()?.to_string_lossy().into_owned();
+                match Self::load(&path) {
+                    Ok(settings) => Some((name, settings)),
+                    Err(err) => {
+                        warn!("Failed to load {}: {}", path.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        settings.sort_by(|(a, _), (b, _)| a.cmp(b));
+        settings
+    }
+
+    #[cfg(test)]
+    pub fn island() -> Self {
+        let mut settings: Self =
+            ron::from_str(include_str!("../../../assets/heightmaps/island.ron")).unwrap();
+        settings.heightmap = format!(
+            "{}/assets/{}",
+            env!("CARGO_MANIFEST_DIR"),
+            settings.heightmap
+        );
+        settings
+    }
+}
+
+struct Grid<T> {
+    size: UVec2,
+    values: Vec<T>,
+}
+
+impl<T: Copy> Grid<T> {
+    fn empty() -> Self {
+        Self {
+            size: UVec2::ZERO,
+            values: Vec::default(),
+        }
+    }
+
+    fn get(&self, pixel: UVec2) -> T {
+        self.values[(pixel.y * self.size.x + pixel.x) as usize]
+    }
+}
+
+impl Grid<f32> {
+    fn sample(&self, pos: Vec2) -> Option<f32> {
+        if self.size.cmpeq(UVec2::ZERO).any()
+            || pos.cmplt(Vec2::ZERO).any()
+            || pos.cmpgt((self.size - UVec2::ONE).as_vec2()).any()
+        {
+            return None;
+        }
+
+        let pixel = pos.floor().as_uvec2();
+        let next = (pixel + UVec2::ONE).min(self.size - UVec2::ONE);
+        let t = pos - pixel.as_vec2();
+        let top = self.get(pixel) * (1. - t.x) + self.get(UVec2::new(next.x, pixel.y)) * t.x;
+        let bottom = self.get(UVec2::new(pixel.x, next.y)) * (1. - t.x) + self.get(next) * t.x;
+        Some(top * (1. - t.y) + bottom * t.y)
+    }
+}
+
+fn asset_path(path: &str) -> PathBuf {
+    Path::new(ASSETS_DIR).join(path)
+}
+
+fn load_heights(path: &Path) -> Result<Grid<f32>, String> {
+    if path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"))
+    {
+        let image =
+            exr::prelude::read_first_flat_layer_from_file(path).map_err(|err| err.to_string())?;
+        let size = image.layer_data.size;
+        let channels = &image.layer_data.channel_data.list;
+        let channel = ["Y", "R"]
+            .iter()
+            .find_map(|name| channels.iter().find(|channel| channel.name.eq(name)))
+            .or_else(|| channels.first())
+            .ok_or("image has no channels")?;
+
+        Ok(Grid {
+            size: UVec2::new(size.width() as u32, size.height() as u32),
+            values: channel.sample_data.values_as_f32().collect(),
+        })
+    } else {
+        let image = image::open(path)
+            .map_err(|err| err.to_string())?
+            .into_luma16();
+
+        Ok(Grid {
+            size: image.dimensions().into(),
+            values: image
+                .pixels()
+                .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32)
+                .collect(),
+        })
+    }
+}
+
+fn load_colors(path: &Path) -> Result<Grid<Color>, String> {
+    let image = image::open(path)
+        .map_err(|err| err.to_string())?
+        .into_rgb8();
+
+    Ok(Grid {
+        size: image.dimensions().into(),
+        values: image
+            .pixels()
+            .map(|pixel| Color::rgb_u8(pixel.0[0], pixel.0[1], pixel.0[2]))
+            .collect(),
+    })
+}
+
+pub struct ImageGenerator {
+    settings: HeightmapSettings,
+    heights: Grid<f32>,
+    colors: Option<Grid<Color>>,
+}
+
+impl ImageGenerator {
+    pub fn new(settings: HeightmapSettings) -> Self {
+        let heights = load_heights(&asset_path(&settings.heightmap)).unwrap_or_else(|err| {
+            warn!("Failed to load heightmap {}: {}", settings.heightmap, err);
+            Grid::empty()
+        });
+
+        let colors = settings.color_map.as_ref().and_then(|color_map| {
+            load_colors(&asset_path(color_map))
+                .map_err(|err| warn!("Failed to load color map {}: {}", color_map, err))
+                .ok()
+        });
+
+        Self {
+            settings,
+            heights,
+            colors,
+        }
+    }
+
+    fn column(&self, column: IVec2) -> Option<(i32, Color)> {
+        let pos = (column - IVec2::new(self.settings.origin.x, self.settings.origin.z)).as_vec2()
+            / self.settings.horizontal_scale;
+        let height = self.heights.sample(pos)?;
+
+        let color = self.colors.as_ref().map_or(self.settings.color, |colors| {
+            let uv = pos / self.heights.size.as_vec2();
+            colors.get(
+                (uv * colors.size.as_vec2())
+                    .as_uvec2()
+                    .min(colors.size - UVec2::ONE),
+            )
+        });
+
+        Some((
+            self.settings.origin.y + (height * self.settings.vertical_scale).round() as i32,
+            color,
+        ))
+    }
+}
+
+impl WorldGenerator for ImageGenerator {
+    fn generate(&self, pos: IVec3) -> Chunk {
+        let mut voxes = vec![None; CHUNK_VOLUME];
+        let chunk_origin = pos * CHUNK_SIZE as i32;
+
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let (height, color) = match (
+                    self.column(IVec2::new(chunk_origin.x + x, chunk_origin.z + z)),
+                    self.settings.fill,
+                ) {
+                    (Some(column), _) => column,
+                    (None, HeightmapFill::Flat { height, color }) => (height, color),
+                    (None, HeightmapFill::Empty) => continue,
+                };
+
+                for y in 0..CHUNK_SIZE as i32 {
+                    let depth = height - (chunk_origin.y + y);
+                    if depth < 0 {
+                        break;
+                    }
+
+                    voxes[Chunk::flatten(IVec3::new(x, y, z))] =
+                        Some(Vox::solid(if depth < SURFACE_DEPTH {
+                            color
+                        } else {
+                            color * SUBSURFACE_SHADE
+                        }));
+                }
+            }
+        }
+
+        Chunk::new(voxes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_scales() {
+        assert!(HeightmapSettings::island().validate().is_ok());
+        for scale in [0., -1., f32::INFINITY, f32::NAN] {
+            let mut settings = HeightmapSettings::island();
+            settings.horizontal_scale = scale;
+            assert!(settings.validate().is_err());
+
+            let mut settings = HeightmapSettings::island();
+            settings.vertical_scale = scale;
+            assert!(settings.validate().is_err());
+        }
+    }
+}
@@ -0,0 +1,221 @@
+mod basic;
+mod biome;
+mod carve;
+mod feature;
+mod heightmap;
+mod ore;
+mod structure;
+mod terrain;
+
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+
+use bevy::prelude::*;
+
+use super::{chunk::Chunk, stage::GenStage};
+
+use self::{
+    basic::{EmptyGenerator, FlatGenerator, SawtoothGenerator},
+    carve::Carver,
+    feature::{Feature, FeatureWriter},
+    heightmap::{HeightmapSettings, ImageGenerator},
+    ore::{OrePass, OreTable},
+    structure::Structures,
+    terrain::{NoiseGenerator, NoiseSettings},
+};
+
+pub use self::{
+    biome::Biome,
+    feature::{Spill, VoxWrites},
+};
+
+pub struct GenPlugin;
+
+impl Plugin for GenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldGenerators>();
+    }
+}
+
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, pos: IVec3) -> Chunk;
+
+    fn biome_at(&self, _pos: IVec3) -> Option<Biome> {
+        None
+    }
+}
+
+pub trait GenPass: Send + Sync {
+    fn apply(&self, pos: IVec3, chunk: &mut Chunk);
+}
+
+pub struct GeneratedChunk {
+    pub stage: GenStage,
+    pub chunk: Chunk,
+    pub spill: Spill,
+}
+
+#[derive(Clone)]
+pub struct ChunkGenerator {
+    terrain: Arc<dyn WorldGenerator>,
+    passes: Vec<Arc<dyn GenPass>>,
+    features: Vec<Arc<dyn Feature>>,
+}
+
+impl ChunkGenerator {
+    pub fn new(terrain: impl WorldGenerator + 'static) -> Self {
+        Self {
+            terrain: Arc::new(terrain),
+            passes: Vec::default(),
+            features: Vec::default(),
+        }
+    }
+
+    pub fn with_pass(mut self, pass: impl GenPass + 'static) -> Self {
+        self.passes.push(Arc::new(pass));
+        self
+    }
+
+    pub fn with_feature(mut self, feature: impl Feature + 'static) -> Self {
+        self.features.push(Arc::new(feature));
+        self
+    }
+
+    pub fn biome_at(&self, pos: IVec3) -> Option<Biome> {
+        self.terrain.biome_at(pos)
+    }
+
+    pub fn generate(&self, pos: IVec3, stage: GenStage, mut chunk: Chunk) -> GeneratedChunk {
+        let mut spill = Spill::default();
+        match stage {
+            GenStage::Terrain => chunk = self.terrain.generate(pos),
+            GenStage::Carve => {
+                for pass in &self.passes {
+                    pass.apply(pos, &mut chunk);
+                }
+            }
+            GenStage::Features => {
+                let mut writer = FeatureWriter::new(pos);
+                for feature in &self.features {
+                    feature.place(pos, &chunk, &mut writer);
+                }
+                spill = writer.finish(&mut chunk);
+            }
+        }
+
+        GeneratedChunk {
+            stage,
+            chunk,
+            spill,
+        }
+    }
+
+    pub fn generate_features(&self, pos: IVec3) -> GeneratedChunk {
+        let terrain = self.generate(pos, GenStage::Terrain, default()).chunk;
+        let carved = self.generate(pos, GenStage::Carve, terrain).chunk;
+        self.generate(pos, GenStage::Features, carved)
+    }
+
+    // Saved chunks keep their stored voxes, but still spill into the chunks around them
+    pub fn generate_saved(&self, pos: IVec3, saved: Chunk) -> GeneratedChunk {
+        GeneratedChunk {
+            chunk: saved,
+            ..self.generate_features(pos)
+        }
+    }
+}
+
+pub const DEFAULT_GENERATOR: &str = "noise";
+const ARCHIPELAGO_SEA_LEVEL: i32 = 24;
+
+type GeneratorFactory = Box<dyn Fn(u64) -> ChunkGenerator + Send + Sync>;
+
+pub struct WorldGenerators(Vec<(String, GeneratorFactory)>);
+
+impl Default for WorldGenerators {
+    fn default() -> Self {
+        Self::new(OreTable::load(), HeightmapSettings::load_all())
+    }
+}
+
+#[cfg(test)]
+impl WorldGenerators {
+    // The generators with their built-in settings, so tests don't depend on edited assets or the
+    // working directory
+    pub fn embedded() -> Self {
+        Self::new(
+            OreTable::default(),
+            vec![("island".to_string(), HeightmapSettings::island())],
+        )
+    }
+}
+
+impl WorldGenerators {
+    pub fn new(ores: OreTable, heightmaps: Vec<(String, HeightmapSettings)>) -> Self {
+        let mut generators = Self(Vec::default());
+        let archipelago_ores = ores.clone();
+        generators
+            .register(DEFAULT_GENERATOR, move |seed| {
+                ChunkGenerator::new(NoiseGenerator::new(seed, NoiseSettings::default()))
+                    .with_pass(Carver::new(seed))
+                    .with_pass(OrePass::new(seed, ores.clone()))
+                    .with_feature(Structures::new(seed))
+            })
+            .register("archipelago", move |seed| {
+                ChunkGenerator::new(NoiseGenerator::new(
+                    seed,
+                    NoiseSettings {
+                        sea_level: ARCHIPELAGO_SEA_LEVEL,
+                        ..default()
+                    },
+                ))
+                .with_pass(Carver::new(seed))
+                .with_pass(OrePass::new(seed, archipelago_ores.clone()))
+                .with_feature(Structures::new(seed))
+            })
+            .register("flat", |_| ChunkGenerator::new(FlatGenerator))
+            .register("empty", |_| ChunkGenerator::new(EmptyGenerator))
+            .register("sawtooth", |_| ChunkGenerator::new(SawtoothGenerator));
+
+        for (name, settings) in heightmaps {
+            generators.register(name, move |_| {
+                ChunkGenerator::new(ImageGenerator::new(settings.clone()))
+            });
+        }
+
+        generators
+    }
+
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        factory: impl Fn(u64) -> ChunkGenerator + Send + Sync + 'static,
+    ) -> &mut Self {
+        let name = name.into();
+        self.0.retain(|(registered, _)| *registered != name);
+        self.0.push((name, Box::new(factory)));
+        self
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn create(&self, name: &str, seed: u64) -> Option<ChunkGenerator> {
+        self.0
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .map(|(_, factory)| factory(seed))
+    }
+}
+
+#[derive(Clone, Deref)]
+pub struct GeneratorName(pub String);
+
+impl Default for GeneratorName {
+    fn default() -> Self {
+        Self(DEFAULT_GENERATOR.to_string())
+    }
+}
@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::game::{
+    chunk::{Chunk, ADJACENTS, CHUNK_SIZE},
+    model::load_ron_or_default,
+    noise::hash,
+    vox::{Material, Vox},
+};
+
+use super::GenPass;
+
+const ORES_PATH: &str = "assets/worldgen/ores.ron";
+const DEFAULT_ORES: &str = include_str!("../../../assets/worldgen/ores.ron");
+const ORE_SALT: u64 = 0x4f52_4553;
+
+#[derive(Clone, Deserialize)]
+pub struct OreConfig {
+    pub name: String,
+    pub material: u16,
+    pub color: Color,
+    pub height: (i32, i32),
+    pub vein_size: (u32, u32),
+    pub veins_per_chunk: f32,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct OreTable {
+    pub ores: Vec<OreConfig>,
+}
+
+impl Default for OreTable {
+    fn default() -> Self {
+        ron::from_str(DEFAULT_ORES).unwrap()
+    }
+}
+
+impl OreConfig {
+    // Settings that would make placing veins panic, or ores that would pass for built-in materials
+    fn validate(&self) -> Result<(), String> {
+        if !(self.veins_per_chunk >= 0. && self.veins_per_chunk.is_finite()) {
+            return Err(format!("{}: veins_per_chunk must be at least 0", self.name));
+        }
+        if self.vein_size.0 > self.vein_size.1 {
+            return Err(format!("{}: vein_size must be (min, max)", self.name));
+        }
+        if Material::BUILT_IN.contains(&Material(self.material)) {
+            return Err(format!(
+                "{}: material {} is reserved for built-in materials",
+                self.name, self.material
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl OreTable {
+    pub fn load() -> Self {
+        load_ron_or_default(ORES_PATH, DEFAULT_ORES, |table: &Self| {
+            table.ores.iter().try_for_each(OreConfig::validate)
+        })
+    }
+}
+
+pub struct OrePass {
+    seed: u64,
+    table: OreTable,
+}
+
+impl OrePass {
+    pub fn new(seed: u64, table: OreTable) -> Self {
+        Self {
+            seed: seed ^ ORE_SALT,
+            table,
+        }
+    }
+
+    fn place_vein(
+        &self,
+        ore: &OreConfig,
+        chunk_origin: IVec3,
+        chunk: &mut Chunk,
+        rng: &mut StdRng,
+    ) {
+        let mut pos = IVec3::new(
+            rng.gen_range(0..CHUNK_SIZE as i32),
+            rng.gen_range(0..CHUNK_SIZE as i32),
+            rng.gen_range(0..CHUNK_SIZE as i32),
+        );
+
+        let world_y = chunk_origin.y + pos.y;
+        if world_y < ore.height.0 || world_y > ore.height.1 {
+            return;
+        }
+
+        for _ in 0..rng.gen_range(ore.vein_size.0..=ore.vein_size.1) {
+            if chunk.is_solid(pos) {
+                chunk.set(pos, Some(Vox::new(ore.color, Material(ore.material))));
+            }
+
+            pos = (pos + ADJACENTS[rng.gen_range(0..ADJACENTS.len())])
+                .clamp(IVec3::ZERO, IVec3::splat(CHUNK_SIZE as i32 - 1));
+        }
+    }
+}
+
+impl GenPass for OrePass {
+    fn apply(&self, pos: IVec3, chunk: &mut Chunk) {
+        if chunk.is_empty() {
+            return;
+        }
+
+        let chunk_origin = pos * CHUNK_SIZE as i32;
+        for (i, ore) in self.table.ores.iter().enumerate() {
+            let chunk_top = chunk_origin.y + CHUNK_SIZE as i32 - 1;
+            if chunk_top < ore.height.0 || chunk_origin.y > ore.height.1 {
+                continue;
+            }
+
+            let mut rng = StdRng::seed_from_u64(hash(self.seed.wrapping_add(i as u64), pos));
+            let veins = ore.veins_per_chunk.floor() as u32
+                + rng.gen_bool(ore.veins_per_chunk.fract() as f64) as u32;
+            for _ in 0..veins {
+                self.place_vein(ore, chunk_origin, chunk, &mut rng);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_ores_are_valid() {
+        let table = OreTable::default();
+        assert!(table.ores.iter().all(|ore| ore.validate().is_ok()));
+    }
+
+    #[test]
+    fn rejects_invalid_ores() {
+        let ore = || OreConfig {
+            name: "test".to_string(),
+            material: 2,
+            color: Color::BLACK,
+            height: (0, 0),
+            vein_size: (1, 2),
+            veins_per_chunk: 1.,
+        };
+        assert!(ore().validate().is_ok());
+        assert!(OreConfig {
+            veins_per_chunk: -1.,
+            ..ore()
+        }
+        .validate()
+        .is_err());
+        assert!(OreConfig {
+            veins_per_chunk: f32::NAN,
+            ..ore()
+        }
+        .validate()
+        .is_err());
+        assert!(OreConfig {
+            vein_size: (3, 2),
+            ..ore()
+        }
+        .validate()
+        .is_err());
+        assert!(OreConfig {
+            material: 0,
+            ..ore()
+        }
+        .validate()
+        .is_err());
+        assert!(OreConfig {
+            material: 1,
+            ..ore()
+        }
+        .validate()
+        .is_err());
+    }
+}
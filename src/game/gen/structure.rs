@@ -0,0 +1,190 @@
+use std::ops::RangeInclusive;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::game::{
+    chunk::{Chunk, CHUNK_SIZE},
+    noise::hash,
+    vox::Vox,
+};
+
+use super::{
+    biome::{Biome, BiomeMap},
+    feature::{Feature, FeatureWriter},
+};
+
+const STRUCTURE_SALT: u64 = 0x5354_5255_4354;
+const ATTEMPTS_PER_CHUNK: usize = 24;
+
+const TRUNK_HEIGHT: RangeInclusive<i32> = 4..=7;
+const CANOPY_RADIUS: RangeInclusive<i32> = 2..=3;
+const TRUNK_COLOR: Color = Color::rgb(0.4, 0.26, 0.13);
+const LEAF_COLOR: Color = Color::rgb(0.18, 0.5, 0.16);
+const PINE_LEAF_COLOR: Color = Color::rgb(0.12, 0.32, 0.2);
+
+const BOULDER_RADIUS: RangeInclusive<i32> = 1..=3;
+const BOULDER_COLOR: Color = Color::rgb(0.52, 0.52, 0.5);
+
+const RUIN_CHANCE: f64 = 1. / 48.;
+const RUIN_SIZE: RangeInclusive<i32> = 5..=11;
+const RUIN_HEIGHT: RangeInclusive<i32> = 2..=5;
+const RUIN_DECAY: f64 = 0.3;
+const RUIN_COLOR: Color = Color::rgb(0.7, 0.62, 0.48);
+
+enum Structure {
+    Tree,
+    Pine,
+    Boulder,
+}
+
+impl Structure {
+    fn choose(biome: Biome, rng: &mut StdRng) -> Option<Self> {
+        let roll = rng.gen::<f32>();
+        match biome {
+            Biome::Forest if roll < 0.5 => Some(Structure::Tree),
+            Biome::Grassland if roll < 0.05 => Some(Structure::Tree),
+            Biome::Grassland | Biome::Desert if roll < 0.08 => Some(Structure::Boulder),
+            Biome::Tundra if roll < 0.1 => Some(Structure::Pine),
+            Biome::Tundra | Biome::Mountains if roll < 0.15 => Some(Structure::Boulder),
+            _ => None,
+        }
+    }
+}
+
+fn surface(chunk: &Chunk, column: IVec2) -> Option<i32> {
+    (0..CHUNK_SIZE as i32 - 1).rev().find(|y| {
+        chunk.is_solid(IVec3::new(column.x, *y, column.y))
+            && chunk.get(IVec3::new(column.x, y + 1, column.y)).is_none()
+    })
+}
+
+fn place_blob(writer: &mut FeatureWriter, center: IVec3, radius: IVec3, color: Color) {
+    let scale = 1. / radius.as_vec3().max(Vec3::splat(0.5));
+    for x in -radius.x..=radius.x {
+        for y in -radius.y..=radius.y {
+            for z in -radius.z..=radius.z {
+                let offset = IVec3::new(x, y, z);
+                if (offset.as_vec3() * scale).length_squared() <= 1. {
+                    writer.place(center + offset, Vox::solid(color));
+                }
+            }
+        }
+    }
+}
+
+pub struct Structures {
+    seed: u64,
+    biomes: BiomeMap,
+}
+
+impl Structures {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed: seed ^ STRUCTURE_SALT,
+            biomes: BiomeMap::new(seed),
+        }
+    }
+
+    fn place_tree(&self, writer: &mut FeatureWriter, root: IVec3, rng: &mut StdRng) {
+        let height = rng.gen_range(TRUNK_HEIGHT);
+        for y in 1..=height {
+            writer.place(root + IVec3::Y * y, Vox::solid(TRUNK_COLOR));
+        }
+
+        let radius = rng.gen_range(CANOPY_RADIUS);
+        place_blob(
+            writer,
+            root + IVec3::Y * (height + 1),
+            IVec3::splat(radius),
+            LEAF_COLOR,
+        );
+    }
+
+    fn place_pine(&self, writer: &mut FeatureWriter, root: IVec3, rng: &mut StdRng) {
+        let height = rng.gen_range(TRUNK_HEIGHT) + 2;
+        for y in 1..=height {
+            writer.place(root + IVec3::Y * y, Vox::solid(TRUNK_COLOR));
+        }
+
+        for y in 3..=height + 1 {
+            let radius = (height + 1 - y) / 2 + 1;
+            place_blob(
+                writer,
+                root + IVec3::Y * y,
+                IVec3::new(radius, 0, radius),
+                PINE_LEAF_COLOR,
+            );
+        }
+    }
+
+    fn place_boulder(&self, writer: &mut FeatureWriter, root: IVec3, rng: &mut StdRng) {
+        let radius = IVec3::new(
+            rng.gen_range(BOULDER_RADIUS),
+            rng.gen_range(BOULDER_RADIUS),
+            rng.gen_range(BOULDER_RADIUS),
+        );
+        place_blob(writer, root, radius, BOULDER_COLOR);
+    }
+
+    fn place_ruin(&self, writer: &mut FeatureWriter, root: IVec3, rng: &mut StdRng) {
+        let size = IVec2::new(rng.gen_range(RUIN_SIZE), rng.gen_range(RUIN_SIZE));
+        let height = rng.gen_range(RUIN_HEIGHT);
+
+        for x in 0..size.x {
+            for z in 0..size.y {
+                if x != 0 && x != size.x - 1 && z != 0 && z != size.y - 1 {
+                    continue;
+                }
+
+                for y in 0..height {
+                    if rng.gen_bool(RUIN_DECAY * y as f64 / height as f64) {
+                        break;
+                    }
+
+                    writer.place(root + IVec3::new(x, y, z), Vox::solid(RUIN_COLOR));
+                }
+            }
+        }
+    }
+}
+
+impl Feature for Structures {
+    fn place(&self, pos: IVec3, chunk: &Chunk, writer: &mut FeatureWriter) {
+        let mut rng = StdRng::seed_from_u64(hash(self.seed, pos));
+        let chunk_origin = pos * CHUNK_SIZE as i32;
+
+        for _ in 0..ATTEMPTS_PER_CHUNK {
+            let column = IVec2::new(
+                rng.gen_range(0..CHUNK_SIZE as i32),
+                rng.gen_range(0..CHUNK_SIZE as i32),
+            );
+            let structure = Structure::choose(
+                self.biomes
+                    .biome_at(IVec2::new(chunk_origin.x, chunk_origin.z) + column),
+                &mut rng,
+            );
+
+            if let (Some(structure), Some(y)) = (structure, surface(chunk, column)) {
+                let root = chunk_origin + IVec3::new(column.x, y, column.y);
+                match structure {
+                    Structure::Tree => self.place_tree(writer, root, &mut rng),
+                    Structure::Pine => self.place_pine(writer, root, &mut rng),
+                    Structure::Boulder => self.place_boulder(writer, root, &mut rng),
+                }
+            }
+        }
+
+        if rng.gen_bool(RUIN_CHANCE) {
+            let column = IVec2::new(
+                rng.gen_range(0..CHUNK_SIZE as i32),
+                rng.gen_range(0..CHUNK_SIZE as i32),
+            );
+
+            if let Some(y) = surface(chunk, column) {
+                let root = chunk_origin + IVec3::new(column.x, y + 1, column.y);
+                self.place_ruin(writer, root, &mut rng);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+
+use crate::game::{
+    chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME},
+    noise::Noise,
+    vox::{Material, Vox},
+};
+
+use super::{
+    biome::{Biome, BiomeMap},
+    WorldGenerator,
+};
+
+const TERRAIN_SCALE: f32 = 256.;
+const TERRAIN_OCTAVES: u32 = 5;
+const SNOW_LINE: i32 = 96;
+const SNOW_COLOR: Color = Color::rgb(0.95, 0.95, 0.97);
+const STONE_COLOR: Color = Color::rgb(0.45, 0.45, 0.45);
+
+const RIVER_SCALE: f32 = 1. / 600.;
+const RIVER_OCTAVES: u32 = 3;
+const RIVER_WIDTH: f32 = 0.04;
+const RIVER_DEPTH: f32 = 6.;
+const LAKE_SCALE: f32 = 1. / 300.;
+const LAKE_OCTAVES: u32 = 2;
+const LAKE_THRESHOLD: f32 = 0.3;
+const LAKE_FALLOFF: f32 = 0.15;
+const LAKE_DEPTH: f32 = 10.;
+const SHORE_DEPTH: i32 = 2;
+const BED_COLOR: Color = Color::rgb(0.62, 0.58, 0.45);
+const WATER_COLOR: Color = Color::rgba(0.15, 0.35, 0.75, 0.6);
+
+#[derive(Clone, Copy)]
+pub struct NoiseSettings {
+    pub sea_level: i32,
+    pub rivers: bool,
+    pub lakes: bool,
+}
+
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            sea_level: 0,
+            rivers: true,
+            lakes: true,
+        }
+    }
+}
+
+struct Column {
+    biome: Biome,
+    height: i32,
+    water_level: i32,
+}
+
+pub struct NoiseGenerator {
+    settings: NoiseSettings,
+    noise: Noise,
+    rivers: Noise,
+    lakes: Noise,
+    biomes: BiomeMap,
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u64, settings: NoiseSettings) -> Self {
+        let noise = Noise::new(seed);
+        Self {
+            settings,
+            noise,
+            rivers: noise.with_offset(4),
+            lakes: noise.with_offset(5),
+            biomes: BiomeMap::new(seed),
+        }
+    }
+
+    fn column(&self, column: IVec2) -> Column {
+        let pos = column.as_vec2();
+        let terrain = self.noise.fbm2(pos / TERRAIN_SCALE, TERRAIN_OCTAVES);
+
+        let height = self
+            .biomes
+            .weights(column)
+            .iter()
+            .map(|(biome, weight)| {
+                let profile = biome.profile();
+                (profile.base_height + terrain * profile.amplitude) * weight
+            })
+            .sum::<f32>();
+
+        let mut carve = 0.;
+        if self.settings.rivers {
+            let river = self.rivers.fbm2(pos * RIVER_SCALE, RIVER_OCTAVES).abs();
+            carve += (1. - river / RIVER_WIDTH).max(0.).powi(2) * RIVER_DEPTH;
+        }
+        if self.settings.lakes {
+            let lake = self.lakes.fbm2(pos * LAKE_SCALE, LAKE_OCTAVES);
+            carve += ((lake - LAKE_THRESHOLD) / LAKE_FALLOFF).clamp(0., 1.) * LAKE_DEPTH;
+        }
+
+        let inland_level = height.floor() as i32 - SHORE_DEPTH;
+        let height = (height - carve).floor() as i32;
+        let water_level = if height < inland_level {
+            inland_level.max(self.settings.sea_level)
+        } else {
+            self.settings.sea_level
+        };
+
+        Column {
+            biome: self.biomes.biome_at(column),
+            height,
+            water_level,
+        }
+    }
+}
+
+fn terrain_color(column: &Column, depth: i32) -> Color {
+    let profile = column.biome.profile();
+    match depth {
+        0 if column.height < column.water_level => BED_COLOR,
+        0 if column.height >= SNOW_LINE => SNOW_COLOR,
+        0 => profile.surface,
+        _ if depth <= profile.subsurface_depth => profile.subsurface,
+        _ => STONE_COLOR,
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, pos: IVec3) -> Chunk {
+        let chunk_origin = pos * CHUNK_SIZE as i32;
+
+        let mut voxes = vec![None; CHUNK_VOLUME];
+        for x in 0..CHUNK_SIZE as i32 {
+            for z in 0..CHUNK_SIZE as i32 {
+                let column = self.column(IVec2::new(chunk_origin.x + x, chunk_origin.z + z));
+                if column.height.max(column.water_level) < chunk_origin.y {
+                    continue;
+                }
+
+                for y in 0..CHUNK_SIZE as i32 {
+                    let world_y = chunk_origin.y + y;
+                    let depth = column.height - world_y;
+                    voxes[Chunk::flatten(IVec3::new(x, y, z))] = if depth >= 0 {
+                        Some(Vox::solid(terrain_color(&column, depth)))
+                    } else if world_y <= column.water_level {
+                        Some(Vox::new(WATER_COLOR, Material::WATER))
+                    } else {
+                        None
+                    };
+                }
+            }
+        }
+
+        Chunk::new(voxes)
+    }
+
+    fn biome_at(&self, pos: IVec3) -> Option<Biome> {
+        Some(self.biomes.biome_at(IVec2::new(pos.x, pos.z)))
+    }
+}
@@ -0,0 +1,116 @@
+use std::{collections::BTreeMap, env, fs};
+
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+
+use crate::game::{chunk::Chunk, stage::GenStage};
+
+use super::{ChunkGenerator, WorldGenerators};
+
+// Regenerate with `UPDATE_GOLDEN=1 cargo test` after intentionally changing generation
+const GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/game/gen/golden.ron");
+const SEEDS: [u64; 3] = [0, 1, 0x5eed];
+
+type Hashes = BTreeMap<String, BTreeMap<u64, Vec<(IVec3, u64)>>>;
+
+fn generate(generator: &ChunkGenerator, pos: IVec3) -> Chunk {
+    [GenStage::Terrain, GenStage::Carve, GenStage::Features]
+        .into_iter()
+        .fold(Chunk::default(), |chunk, stage| {
+            generator.generate(pos, stage, chunk).chunk
+        })
+}
+
+fn positions() -> impl Iterator<Item = IVec3> {
+    (-1..=1).flat_map(|x| (-2..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+}
+
+fn hashes() -> Hashes {
+    let generators = WorldGenerators::embedded();
+    generators
+        .names()
+        .map(|name| {
+            let seeds = SEEDS
+                .iter()
+                .map(|seed| {
+                    let generator = generators.create(name, *seed).unwrap();
+                    let chunks = positions()
+                        .map(|pos| (pos, generate(&generator, pos).content_hash()))
+                        .collect();
+                    (*seed, chunks)
+                })
+                .collect();
+            (name.to_string(), seeds)
+        })
+        .collect()
+}
+
+#[test]
+fn chunks_match_golden_hashes() {
+    let hashes = hashes();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(
+            GOLDEN_PATH,
+            ron::ser::to_string_pretty(&hashes, PrettyConfig::default()).unwrap() + "\n",
+        )
+        .unwrap();
+        return;
+    }
+
+    let golden: Hashes = ron::from_str(&fs::read_to_string(GOLDEN_PATH).unwrap()).unwrap();
+    assert_eq!(
+        hashes.keys().collect::<Vec<_>>(),
+        golden.keys().collect::<Vec<_>>(),
+        "registered generators differ from the golden hashes"
+    );
+
+    let mut mismatches = Vec::default();
+    for (name, seeds) in &golden {
+        for (seed, chunks) in seeds {
+            let actual = &hashes[name][seed];
+            assert_eq!(
+                chunks.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(),
+                actual.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(),
+                "\"{}\" with seed {} generated different chunks from the golden hashes",
+                name,
+                seed
+            );
+
+            for ((pos, expected), (_, actual)) in chunks.iter().zip(actual) {
+                if expected != actual {
+                    mismatches.push(format!(
+                        "\"{}\" with seed {} at {}: expected {:#x}, got {:#x}",
+                        name, seed, pos, expected, actual
+                    ));
+                }
+            }
+        }
+    }
+
+    assert!(
+        mismatches.is_empty(),
+        "generated chunks changed:\n{}",
+        mismatches.join("\n")
+    );
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let generators = WorldGenerators::embedded();
+    for name in generators.names() {
+        let (a, b) = (
+            generators.create(name, 7).unwrap(),
+            generators.create(name, 7).unwrap(),
+        );
+
+        for pos in positions() {
+            assert_eq!(
+                generate(&a, pos).content_hash(),
+                generate(&b, pos).content_hash(),
+                "\"{}\" generated chunk {} differently",
+                name,
+                pos
+            );
+        }
+    }
+}
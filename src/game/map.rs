@@ -1,78 +1,693 @@
-use std::mem::take;
+use std::{io, mem::take};
 
 use bevy::{
     prelude::*,
     render::camera::Camera3d,
-    tasks::AsyncComputeTaskPool,
-    utils::{HashMap, HashSet},
+    tasks::{AsyncComputeTaskPool, IoTaskPool, Task},
+    utils::HashMap,
 };
+use futures_lite::future::{block_on, poll_once};
+use rand::random;
 
-use crate::state::GameState;
+use crate::state::{GameState, WorldError};
 
-use super::{chunk::Chunk, player::ChunkPos, render::RemovedChunks, DespawnQueue};
+use super::{
+    cache::{ChunkCache, ChunkCacheBudget},
+    chunk::{Chunk, ADJACENTS, CHUNK_AREA, CHUNK_SIZE},
+    gen::{
+        Biome, ChunkGenerator, GeneratedChunk, GeneratorName, Spill, VoxWrites, WorldGenerators,
+    },
+    model::{Orientation, VoxModel},
+    player::ChunkPos,
+    render::RemovedChunks,
+    save::{now, unused_name, ChunkSnapshot, CurrentWorld, WorldMeta, WorldSave},
+    stage::{neighbors, ChunkStage, GenStage},
+    vox::Vox,
+    DespawnQueue,
+};
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::Game).with_system(init_map))
-            .add_system_set(SystemSet::on_update(GameState::Game).with_system(load_chunks));
+        app.init_resource::<ChunkCacheBudget>()
+            .add_system_set(SystemSet::on_enter(GameState::Game).with_system(init_map))
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    .with_system(load_chunks)
+                    .with_system(autosave),
+            );
+
+        #[cfg(feature = "inspector")]
+        app.add_system_set(SystemSet::on_update(GameState::Game).with_system(log_biome));
     }
 }
 
-#[derive(Default)]
 pub struct Map {
     chunks: HashMap<IVec3, Entity>,
+    stages: HashMap<IVec3, ChunkStage>,
+    targets: HashMap<IVec3, ChunkStage>,
+    tasks: HashMap<IVec3, Task<GeneratedChunk>>,
     removed_chunks: Vec<IVec3>,
+    pending_writes: HashMap<IVec3, HashMap<IVec3, VoxWrites>>,
+    // Which columns sunlight leaves each lit chunk through at the bottom
+    sky: HashMap<IVec3, Vec<bool>>,
+    edits: HashMap<IVec3, VoxWrites>,
+    center: IVec3,
+    retarget: bool,
+    generator_name: String,
+    generator: ChunkGenerator,
+    save: WorldSave,
+    cache: ChunkCache,
+    unsaved: HashMap<IVec3, ChunkSnapshot>,
+    unsaved_meta: Option<WorldMeta>,
+    saving: HashMap<IVec3, ChunkSnapshot>,
+    saving_meta: Option<WorldMeta>,
+    save_task: Option<Task<io::Result<()>>>,
+    // Set when a save fails, so it's retried with the next autosave rather than every frame
+    save_failed: bool,
+    autosave_timer: Timer,
 }
 
+#[derive(Clone, Copy, Deref)]
+pub struct WorldSeed(pub u64);
+
 const RENDER_RADIUS: i32 = 4;
 pub const RENDER_RADIUS_F32: f32 = RENDER_RADIUS as f32;
+const AUTOSAVE_INTERVAL: f32 = 60.;
+
+// A chunk that was never loaded, with the writes its loaded neighbors made to it. Writes from
+// neighbors that were never loaded either are missing.
+fn generate_unloaded(generator: &ChunkGenerator, pos: IVec3, writes: &[VoxWrites]) -> Chunk {
+    let mut chunk = generator.generate_features(pos).chunk;
+    for writes in writes {
+        chunk.fill_empty(writes);
+    }
+    chunk
+}
+
+// Adds the chunk's voxes between `min` and `max` inclusive, relative to `min`
+fn push_region_voxes(
+    voxes: &mut Vec<(IVec3, Vox)>,
+    chunk_pos: IVec3,
+    chunk_voxes: &[Option<Vox>],
+    min: IVec3,
+    max: IVec3,
+) {
+    for (i, vox) in chunk_voxes.iter().enumerate() {
+        let pos = chunk_pos * CHUNK_SIZE as i32 + Chunk::expand(i);
+        if let Some(vox) = vox {
+            if pos.cmpge(min).all() && pos.cmple(max).all() {
+                voxes.push((pos - min, vox.clone()));
+            }
+        }
+    }
+}
+
+fn write_save(
+    save: &WorldSave,
+    chunks: &[(IVec3, ChunkSnapshot)],
+    meta: Option<&WorldMeta>,
+) -> io::Result<()> {
+    save.save_chunks(chunks)?;
+    if let Some(meta) = meta {
+        save.write_meta(meta)?;
+    }
+
+    Ok(())
+}
 
 impl Map {
+    fn new(
+        generator_name: String,
+        generator: ChunkGenerator,
+        save: WorldSave,
+        cache_budget: usize,
+    ) -> Self {
+        Self {
+            chunks: default(),
+            stages: default(),
+            targets: default(),
+            tasks: default(),
+            removed_chunks: default(),
+            pending_writes: default(),
+            sky: default(),
+            edits: default(),
+            center: IVec3::ZERO,
+            retarget: false,
+            generator_name,
+            generator,
+            save,
+            cache: ChunkCache::new(cache_budget),
+            unsaved: default(),
+            unsaved_meta: None,
+            saving: default(),
+            saving_meta: None,
+            save_task: None,
+            save_failed: false,
+            autosave_timer: Timer::from_seconds(AUTOSAVE_INTERVAL, true),
+        }
+    }
+
+    pub fn generator_name(&self) -> &str {
+        &self.generator_name
+    }
+
+    // For gameplay systems; only the inspector build's biome log uses it so far
+    #[cfg_attr(not(feature = "inspector"), allow(dead_code))]
+    pub fn biome_at(&self, pos: IVec3) -> Option<Biome> {
+        self.generator.biome_at(pos)
+    }
+
+    fn neighbors_at(&self, pos: IVec3, stage: ChunkStage) -> bool {
+        neighbors(pos).all(|neighbor| {
+            self.stages
+                .get(&neighbor)
+                .is_some_and(|neighbor_stage| *neighbor_stage >= stage)
+        })
+    }
+
+    // Sunlight reaching the top of the chunk through every loaded chunk above it
+    fn sky_above(&self, chunks: &Query<&mut Chunk>, pos: IVec3) -> Vec<bool> {
+        let mut sky = vec![true; CHUNK_AREA];
+        let mut above = pos + IVec3::Y;
+        while let Some(chunk) = self
+            .stages
+            .get(&above)
+            .filter(|stage| **stage >= ChunkStage::Generated(GenStage::Features))
+            .and_then(|_| chunks.get(self.chunks[&above]).ok())
+        {
+            chunk.block_sky(&mut sky);
+            above += IVec3::Y;
+        }
+
+        sky
+    }
+
+    fn spawn_stage(
+        &mut self,
+        pos: IVec3,
+        stage: GenStage,
+        chunk: Chunk,
+        thread_pool: &AsyncComputeTaskPool,
+    ) {
+        let generator = self.generator.clone();
+        let save = self.save.clone();
+        let unsaved = match stage {
+            GenStage::Terrain => self.unsaved_chunk(pos),
+            _ => None,
+        };
+        self.stages.remove(&pos);
+        self.tasks.insert(
+            pos,
+            thread_pool.spawn(async move {
+                match stage {
+                    GenStage::Terrain => match unsaved.or_else(|| save.load_chunk(pos)) {
+                        Some(saved) => generator.generate_saved(pos, saved),
+                        None => generator.generate(pos, stage, chunk),
+                    },
+                    _ => generator.generate(pos, stage, chunk),
+                }
+            }),
+        );
+    }
+
     fn load_chunks(
         &mut self,
         commands: &mut Commands,
+        chunks: &mut Query<&mut Chunk>,
         pos: IVec3,
         thread_pool: &AsyncComputeTaskPool,
         despawn_queue: &mut DespawnQueue,
     ) {
-        let mut expected_chunks = HashSet::default();
+        // Chunks within the render radius are generated fully, and each ring around them only
+        // as far as the ring inside needs its neighbors to be
+        let mut targets = HashMap::default();
         for x in -RENDER_RADIUS..=RENDER_RADIUS {
             for y in -RENDER_RADIUS..=RENDER_RADIUS {
                 for z in -RENDER_RADIUS..=RENDER_RADIUS {
                     if ((x * x + y * y + z * z) as f32) < RENDER_RADIUS_F32 * RENDER_RADIUS_F32 {
-                        expected_chunks.insert(pos + IVec3::new(x, y, z));
+                        targets.insert(pos + IVec3::new(x, y, z), ChunkStage::Ready);
                     }
                 }
             }
         }
 
+        // Chunks with edits waiting on them stay loaded until the edits are applied
+        for pos in self.edits.keys() {
+            targets.entry(*pos).or_insert(ChunkStage::Light);
+        }
+
+        for stage in [ChunkStage::Light, ChunkStage::Generated(GenStage::Features)] {
+            let ring = targets
+                .keys()
+                .flat_map(|pos| neighbors(*pos))
+                .collect::<Vec<_>>();
+            for pos in ring {
+                targets.entry(pos).or_insert(stage);
+            }
+        }
+
         let mut to_remove = Vec::default();
         for (chunk_pos, chunk_e) in self.chunks.iter() {
-            if !expected_chunks.contains(chunk_pos) {
+            if !targets.contains_key(chunk_pos) {
                 to_remove.push(*chunk_pos);
-                expected_chunks.remove(chunk_pos);
                 despawn_queue.push(*chunk_e);
             }
         }
 
         for pos in to_remove {
-            self.chunks.remove(&pos);
+            let chunk_e = self.chunks.remove(&pos).unwrap();
+            let stage = self.stages.remove(&pos);
+            self.sky.remove(&pos);
+            let spill = self.take_spill(pos);
+            self.tasks.remove(&pos);
             self.removed_chunks.push(pos);
+
+            if let Ok(mut chunk) = chunks.get_mut(chunk_e) {
+                let mut chunk = take(&mut *chunk);
+                if stage.is_some_and(|stage| stage >= ChunkStage::Generated(GenStage::Features)) {
+                    let generated = GeneratedChunk {
+                        stage: GenStage::Features,
+                        chunk,
+                        spill,
+                    };
+                    for (pos, mut evicted) in self.cache.insert(pos, generated) {
+                        self.queue_save(pos, &mut evicted.chunk);
+                    }
+                } else {
+                    self.queue_save(pos, &mut chunk);
+                }
+            }
+        }
+
+        for chunk_pos in targets.keys() {
+            if !self.chunks.contains_key(chunk_pos) {
+                self.chunks
+                    .insert(*chunk_pos, commands.spawn().insert(Chunk::default()).id());
+
+                // Cached chunks go through a task like any other, so they're only restored once
+                // their entity exists
+                match self.cache.remove(*chunk_pos) {
+                    Some(cached) => {
+                        self.tasks
+                            .insert(*chunk_pos, thread_pool.spawn(async move { cached }));
+                    }
+                    None => self.spawn_stage(*chunk_pos, GenStage::Terrain, default(), thread_pool),
+                }
+            }
+        }
+
+        self.targets = targets;
+        self.center = pos;
+        self.retarget = false;
+    }
+
+    pub fn resolve_chunks(&mut self, chunks: &mut Query<&mut Chunk>) {
+        let resolved = self
+            .tasks
+            .iter_mut()
+            .filter(|(pos, _)| chunks.get(self.chunks[*pos]).is_ok())
+            .filter_map(|(pos, task)| block_on(poll_once(task)).map(|generated| (*pos, generated)))
+            .collect::<Vec<_>>();
+
+        for (
+            pos,
+            GeneratedChunk {
+                stage,
+                mut chunk,
+                spill,
+            },
+        ) in resolved
+        {
+            self.tasks.remove(&pos);
+
+            if stage == GenStage::Features {
+                if !chunk.is_modified() {
+                    self.apply_pending_writes(pos, &mut chunk);
+                }
+
+                self.add_pending_writes(pos, spill, chunks);
+            }
+
+            *chunks.get_mut(self.chunks[&pos]).unwrap() = chunk;
+            self.stages.insert(pos, ChunkStage::Generated(stage));
+
+            // The chunk below is lit through this one
+            self.demote(pos - IVec3::Y, ChunkStage::Generated(GenStage::Features));
+        }
+    }
+
+    pub fn advance_chunks(
+        &mut self,
+        chunks: &mut Query<&mut Chunk>,
+        thread_pool: &AsyncComputeTaskPool,
+        ready_limit: usize,
+    ) {
+        self.apply_edits(chunks);
+
+        let advancing = self
+            .stages
+            .iter()
+            .filter(|(pos, stage)| self.targets.get(pos).is_some_and(|target| target > stage))
+            .filter_map(|(pos, stage)| Some((*pos, stage.next()?)))
+            .collect::<Vec<_>>();
+
+        let mut ready_count = 0;
+        for (pos, stage) in advancing {
+            let chunk_e = self.chunks[&pos];
+            match stage {
+                ChunkStage::Generated(stage) => {
+                    let chunk = take(&mut *chunks.get_mut(chunk_e).unwrap());
+                    self.spawn_stage(pos, stage, chunk, thread_pool);
+                }
+                ChunkStage::Light => {
+                    if !self.neighbors_at(pos, ChunkStage::Generated(GenStage::Features)) {
+                        continue;
+                    }
+
+                    let sky = self.sky_above(chunks, pos);
+                    let below = chunks.get_mut(chunk_e).unwrap().update_light(&sky);
+                    if self.sky.insert(pos, below.clone()) != Some(below) {
+                        self.demote(pos - IVec3::Y, ChunkStage::Generated(GenStage::Features));
+                    }
+                    self.stages.insert(pos, stage);
+                }
+                ChunkStage::Ready => {
+                    if ready_count >= ready_limit || !self.neighbors_at(pos, ChunkStage::Light) {
+                        continue;
+                    }
+
+                    let faces = ADJACENTS
+                        .iter()
+                        .map(|adj| chunks.get(self.chunks[&(pos + *adj)]).unwrap().face(-*adj))
+                        .collect::<Vec<_>>();
+                    chunks.get_mut(chunk_e).unwrap().update_visibility(&faces);
+                    self.stages.insert(pos, stage);
+                    ready_count += 1;
+                }
+            }
+        }
+    }
+
+    pub fn add_pending_writes(
+        &mut self,
+        source: IVec3,
+        spill: Spill,
+        chunks: &mut Query<&mut Chunk>,
+    ) {
+        for (target, writes) in spill {
+            // Chunks that haven't placed their own features yet, or whose entity doesn't have its
+            // chunk yet, get these writes once they do, and modified chunks already contain them
+            let loaded = self
+                .chunks
+                .get(&target)
+                .and_then(|chunk_e| chunks.get_mut(*chunk_e).ok());
+            if let (Some(&stage), Some(mut chunk)) = (self.stages.get(&target), loaded) {
+                if stage >= ChunkStage::Generated(GenStage::Features)
+                    && !chunk.is_modified()
+                    && chunk.fill_empty(&writes)
+                {
+                    self.demote(target, ChunkStage::Generated(GenStage::Features));
+                    self.demote(target - IVec3::Y, ChunkStage::Generated(GenStage::Features));
+                }
+            }
+
+            self.pending_writes
+                .entry(target)
+                .or_default()
+                .insert(source, writes);
+        }
+    }
+
+    pub fn apply_pending_writes(&self, pos: IVec3, chunk: &mut Chunk) {
+        if let Some(writes) = self.pending_writes.get(&pos) {
+            for writes in writes.values() {
+                chunk.fill_empty(writes);
+            }
+        }
+    }
+
+    // The voxes of chunks that are loaded, cached or waiting to be saved
+    fn memory_voxes<'a>(
+        &'a self,
+        chunks: &'a Query<&Chunk>,
+        pos: IVec3,
+    ) -> Option<&'a [Option<Vox>]> {
+        let loaded = self
+            .stages
+            .get(&pos)
+            .filter(|stage| **stage >= ChunkStage::Generated(GenStage::Features))
+            .and_then(|_| chunks.get(self.chunks[&pos]).ok());
+        if let Some(chunk) = loaded.or_else(|| self.cache.get(pos)) {
+            return Some(chunk.voxes());
+        }
+
+        self.unsaved
+            .get(&pos)
+            .or_else(|| self.saving.get(&pos))
+            .map(|voxes| &**voxes)
+    }
+
+    fn pending_writes_to(&self, pos: IVec3) -> Vec<VoxWrites> {
+        self.pending_writes
+            .get(&pos)
+            .map(|writes| writes.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn generated_chunk(&self, pos: IVec3) -> Chunk {
+        generate_unloaded(&self.generator, pos, &self.pending_writes_to(pos))
+    }
+
+    // The voxes between `min` and `max` inclusive, relative to `min`. Chunks in memory are read
+    // now, while the returned closure loads or generates the rest, so it can run on a task.
+    pub fn region(
+        &self,
+        chunks: &Query<&Chunk>,
+        min: IVec3,
+        max: IVec3,
+    ) -> impl FnOnce() -> VoxModel + Send + 'static {
+        let chunk_size = CHUNK_SIZE as f32;
+        let min_chunk = (min.as_vec3() / chunk_size).floor().as_ivec3();
+        let max_chunk = (max.as_vec3() / chunk_size).floor().as_ivec3();
+
+        let mut voxes = Vec::new();
+        let mut unloaded = Vec::new();
+        for x in min_chunk.x..=max_chunk.x {
+            for y in min_chunk.y..=max_chunk.y {
+                for z in min_chunk.z..=max_chunk.z {
+                    let chunk_pos = IVec3::new(x, y, z);
+                    match self.memory_voxes(chunks, chunk_pos) {
+                        Some(chunk_voxes) => {
+                            push_region_voxes(&mut voxes, chunk_pos, chunk_voxes, min, max)
+                        }
+                        None => unloaded.push((chunk_pos, self.pending_writes_to(chunk_pos))),
+                    }
+                }
+            }
+        }
+
+        let generator = self.generator.clone();
+        let save = self.save.clone();
+        move || {
+            for (chunk_pos, writes) in unloaded {
+                let chunk = save
+                    .load_chunk(chunk_pos)
+                    .unwrap_or_else(|| generate_unloaded(&generator, chunk_pos, &writes));
+                push_region_voxes(&mut voxes, chunk_pos, chunk.voxes(), min, max);
+            }
+
+            VoxModel {
+                size: max - min + IVec3::ONE,
+                voxes,
+            }
+        }
+    }
+
+    // Positions `model`'s bounds at `pos` after rotating it. Edits are applied once their chunks
+    // and their chunks' neighbors have placed their features, so features can't overwrite them.
+    pub fn place_model(&mut self, model: &VoxModel, pos: IVec3, orientation: Orientation) {
+        for (vox_pos, vox) in model.oriented(orientation).voxes {
+            let world_pos = pos + vox_pos;
+            let chunk_pos = (world_pos.as_vec3() / CHUNK_SIZE as f32).floor().as_ivec3();
+            self.edits
+                .entry(chunk_pos)
+                .or_default()
+                .push((world_pos - chunk_pos * CHUNK_SIZE as i32, vox));
+        }
+
+        self.retarget = true;
+    }
+
+    fn demote(&mut self, pos: IVec3, stage: ChunkStage) {
+        if let Some(current) = self.stages.get_mut(&pos) {
+            *current = stage.min(*current);
+        }
+    }
+
+    fn apply_edits(&mut self, chunks: &mut Query<&mut Chunk>) {
+        let applicable = self
+            .edits
+            .keys()
+            .filter(|pos| {
+                self.stages
+                    .get(pos)
+                    .is_some_and(|stage| *stage >= ChunkStage::Generated(GenStage::Features))
+                    && self.neighbors_at(**pos, ChunkStage::Generated(GenStage::Features))
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        for pos in &applicable {
+            let writes = self.edits.remove(pos).unwrap();
+            chunks.get_mut(self.chunks[pos]).unwrap().edit(&writes);
+
+            // The chunk below is lit through this one, and adjacent chunks hide faces against it
+            self.demote(*pos, ChunkStage::Generated(GenStage::Features));
+            self.demote(*pos - IVec3::Y, ChunkStage::Generated(GenStage::Features));
+            for adj in ADJACENTS {
+                self.demote(*pos + *adj, ChunkStage::Light);
+            }
+        }
+
+        // Lets the chunks that were only loaded for their edits unload
+        self.retarget |= !applicable.is_empty();
+    }
+
+    // Removes the writes a chunk's features made to its neighbors, so they can be restored with it
+    fn take_spill(&mut self, source: IVec3) -> Spill {
+        let mut spill = Spill::default();
+        self.pending_writes.retain(|target, writes| {
+            if let Some(writes) = writes.remove(&source) {
+                spill.insert(*target, writes);
+            }
+            !writes.is_empty()
+        });
+
+        spill
+    }
+
+    fn queue_save(&mut self, pos: IVec3, chunk: &mut Chunk) {
+        if chunk.take_unsaved() {
+            self.unsaved.insert(pos, chunk.voxes().into());
+        }
+    }
+
+    fn queue_modified(&mut self, chunks: &mut Query<&mut Chunk>) {
+        for (pos, chunk_e) in &self.chunks {
+            if let Ok(mut chunk) = chunks.get_mut(*chunk_e) {
+                if chunk.take_unsaved() {
+                    self.unsaved.insert(*pos, chunk.voxes().into());
+                }
+            }
+        }
+
+        for (pos, chunk) in self.cache.chunks_mut() {
+            if chunk.take_unsaved() {
+                self.unsaved.insert(pos, chunk.voxes().into());
+            }
+        }
+    }
+
+    // Chunks that were unloaded but aren't on disk yet
+    fn unsaved_chunk(&self, pos: IVec3) -> Option<Chunk> {
+        self.unsaved
+            .get(&pos)
+            .or_else(|| self.saving.get(&pos))
+            .map(|voxes| Chunk::loaded(voxes.to_vec()))
+    }
+
+    // A failed save's chunks and metadata go back in the queue, unless newer ones replaced them
+    fn finish_save(&mut self, result: io::Result<()>) {
+        let saving = take(&mut self.saving);
+        let meta = self.saving_meta.take();
+        if let Err(err) = result {
+            warn!("Failed to save world, retrying with the next save: {}", err);
+            for (pos, voxes) in saving {
+                self.unsaved.entry(pos).or_insert(voxes);
+            }
+            self.unsaved_meta = self.unsaved_meta.take().or(meta);
+            self.save_failed = true;
+        }
+    }
+
+    // Only one save runs at a time, so saves reach the disk in the order they were queued
+    pub fn update_saves(&mut self, thread_pool: &IoTaskPool) {
+        if let Some(task) = &mut self.save_task {
+            let result = match block_on(poll_once(task)) {
+                Some(result) => result,
+                None => return,
+            };
+
+            self.save_task = None;
+            self.finish_save(result);
+        }
+
+        if self.save_failed || (self.unsaved.is_empty() && self.unsaved_meta.is_none()) {
+            return;
+        }
+
+        self.saving = take(&mut self.unsaved);
+        self.saving_meta = self.unsaved_meta.take();
+        let chunks = self
+            .saving
+            .iter()
+            .map(|(pos, voxes)| (*pos, voxes.clone()))
+            .collect::<Vec<_>>();
+        let meta = self.saving_meta.clone();
+        let save = self.save.clone();
+        self.save_task =
+            Some(thread_pool.spawn(async move { write_save(&save, &chunks, meta.as_ref()) }));
+    }
+
+    // Edits whose chunks never finished loading go on top of whatever the chunks would have
+    // loaded as
+    fn apply_remaining_edits(&mut self, chunks: &mut Query<&mut Chunk>) {
+        for (pos, writes) in take(&mut self.edits) {
+            let loaded = self
+                .stages
+                .get(&pos)
+                .filter(|stage| **stage >= ChunkStage::Generated(GenStage::Features))
+                .and_then(|_| chunks.get_mut(self.chunks[&pos]).ok());
+            if let Some(mut chunk) = loaded {
+                chunk.edit(&writes);
+                continue;
+            }
+
+            let mut chunk = match self.cache.remove(pos) {
+                Some(cached) => cached.chunk,
+                None => self
+                    .unsaved_chunk(pos)
+                    .or_else(|| self.save.load_chunk(pos))
+                    .unwrap_or_else(|| self.generated_chunk(pos)),
+            };
+            chunk.edit(&writes);
+            self.queue_save(pos, &mut chunk);
+        }
+    }
+
+    // Blocks until every modified chunk, every edit and the metadata are on disk. If that fails,
+    // they stay queued for the next save.
+    pub fn save(&mut self, chunks: &mut Query<&mut Chunk>, meta: &WorldMeta) -> io::Result<()> {
+        if let Some(task) = self.save_task.take() {
+            let result = block_on(task);
+            self.finish_save(result);
         }
 
-        for chunk_pos in expected_chunks {
-            if !self.chunks.contains_key(&chunk_pos) {
-                self.chunks.insert(
-                    chunk_pos,
-                    commands
-                        .spawn()
-                        .insert(thread_pool.spawn(async move { Chunk::generate(chunk_pos) }))
-                        .id(),
-                );
+        self.apply_remaining_edits(chunks);
+        self.queue_modified(chunks);
+
+        let chunks = take(&mut self.unsaved).into_iter().collect::<Vec<_>>();
+        let result = write_save(&self.save, &chunks, Some(meta));
+        match result {
+            Ok(()) => self.unsaved_meta = None,
+            Err(_) => {
+                self.unsaved.extend(chunks);
+                self.unsaved_meta = Some(meta.clone());
             }
         }
+        result
     }
 
     pub fn extract(
@@ -82,6 +697,10 @@ impl Map {
         removed_chunks: &mut RemovedChunks,
     ) {
         for (pos, chunk_e) in self.chunks.iter() {
+            if self.stages.get(pos) != Some(&ChunkStage::Ready) {
+                continue;
+            }
+
             if let Ok(mut chunk) = chunks.get_mut(*chunk_e) {
                 chunk.extract(commands, *chunk_e, *pos);
             }
@@ -91,18 +710,123 @@ impl Map {
     }
 }
 
-fn init_map(mut commands: Commands) {
-    commands.init_resource::<Map>();
+const NEW_WORLD_NAME: &str = "New World";
+
+fn init_map(
+    mut commands: Commands,
+    world: Option<Res<CurrentWorld>>,
+    seed: Option<Res<WorldSeed>>,
+    generator_name: Option<Res<GeneratorName>>,
+    generators: Res<WorldGenerators>,
+    cache_budget: Res<ChunkCacheBudget>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let mut world = world.map_or_else(
+        || {
+            let meta = WorldMeta::new(
+                unused_name(NEW_WORLD_NAME),
+                seed.map_or_else(random, |seed| **seed),
+                generator_name.map_or_else(default, |name| name.clone()).0,
+            );
+            CurrentWorld {
+                save: WorldSave::unused(&meta.name),
+                meta,
+            }
+        },
+        |world| world.clone(),
+    );
+
+    let generator = match generators.create(&world.meta.generator, world.meta.seed) {
+        Some(generator) => generator,
+        None => {
+            let message = format!("No world generator named \"{}\"", world.meta.generator);
+            warn!("Failed to open world \"{}\": {}", world.meta.name, message);
+            commands.remove_resource::<WorldSeed>();
+            commands.remove_resource::<GeneratorName>();
+            commands.remove_resource::<CurrentWorld>();
+            commands.insert_resource(WorldError(message));
+            state.set(GameState::MainMenu).unwrap();
+            return;
+        }
+    };
+
+    world.meta.last_played = now();
+    if let Err(err) = world.save.write_meta(&world.meta) {
+        warn!("Failed to save world metadata: {}", err);
+    }
+
+    let map = Map::new(
+        world.meta.generator.clone(),
+        generator,
+        world.save.clone(),
+        **cache_budget,
+    );
+    info!(
+        "Opened world \"{}\" with seed {} and generator \"{}\"",
+        world.meta.name,
+        world.meta.seed,
+        map.generator_name()
+    );
+
+    commands.insert_resource(map);
+    commands.insert_resource(WorldSeed(world.meta.seed));
+    commands.insert_resource(GeneratorName(world.meta.generator.clone()));
+    commands.insert_resource(world);
 }
 
 fn load_chunks(
     mut commands: Commands,
+    mut chunks: Query<&mut Chunk>,
     players: Query<&ChunkPos, (With<Camera3d>, Changed<ChunkPos>)>,
     thread_pool: Res<AsyncComputeTaskPool>,
     mut map: ResMut<Map>,
     mut despawn_queue: ResMut<DespawnQueue>,
 ) {
-    for pos in players.iter() {
-        map.load_chunks(&mut commands, **pos, &thread_pool, &mut despawn_queue);
+    let pos = players.iter().last().map(|pos| **pos);
+    if pos.is_some() || map.retarget {
+        let pos = pos.unwrap_or(map.center);
+        map.load_chunks(
+            &mut commands,
+            &mut chunks,
+            pos,
+            &thread_pool,
+            &mut despawn_queue,
+        );
+    }
+}
+
+fn autosave(
+    mut chunks: Query<&mut Chunk>,
+    world: Res<CurrentWorld>,
+    time: Res<Time>,
+    thread_pool: Res<IoTaskPool>,
+    mut map: ResMut<Map>,
+) {
+    if map.autosave_timer.tick(time.delta()).just_finished() {
+        let mut meta = world.meta.clone();
+        meta.last_played = now();
+        map.unsaved_meta = Some(meta);
+        map.queue_modified(&mut chunks);
+        map.save_failed = false;
+    }
+
+    map.update_saves(&thread_pool);
+}
+
+#[cfg(feature = "inspector")]
+fn log_biome(
+    players: Query<&Transform, (With<Camera3d>, Changed<Transform>)>,
+    map: Res<Map>,
+    mut last_biome: Local<Option<Biome>>,
+) {
+    for tf in players.iter() {
+        let biome = map.biome_at(tf.translation.floor().as_ivec3());
+        if biome != *last_biome {
+            if let Some(biome) = biome {
+                info!("Entered {:?} biome", biome);
+            }
+
+            *last_biome = biome;
+        }
     }
 }
@@ -0,0 +1,115 @@
+use std::{fmt, io};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::{chunk::CHUNK_SIZE, save::WorldSave};
+
+pub const WORLD_FORMAT: u32 = 3;
+
+// Worlds saved before formats were versioned
+pub fn legacy_format() -> u32 {
+    1
+}
+
+pub fn legacy_chunk_size() -> u32 {
+    32
+}
+
+#[derive(Debug)]
+pub enum OpenWorldError {
+    NewerFormat(u32),
+    ChunkSize(u32),
+    Io(io::Error),
+}
+
+impl fmt::Display for OpenWorldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NewerFormat(format) => write!(
+                f,
+                "world was saved by a newer version of voxmod (format {}, but only formats up to {} \
+                are supported)",
+                format, WORLD_FORMAT
+            ),
+            Self::ChunkSize(size) => write!(
+                f,
+                "world uses {} voxel chunks, but this version uses {} voxel chunks",
+                size, CHUNK_SIZE
+            ),
+            Self::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for OpenWorldError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+// Only the format is read up front, since the rest of the metadata may not match `WorldMeta` until
+// the world is migrated
+#[derive(Deserialize)]
+struct FormatHeader {
+    #[serde(default = "legacy_format")]
+    format: u32,
+}
+
+struct Migration {
+    from: u32,
+    migrate: fn(&WorldSave) -> io::Result<()>,
+}
+
+// Each step upgrades a world from `from` to `from + 1`, and must be safe to run again if the game
+// dies partway through it
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 1,
+        migrate: record_format,
+    },
+    Migration {
+        from: 2,
+        migrate: share_chunks,
+    },
+];
+
+fn record_format(save: &WorldSave) -> io::Result<()> {
+    let mut meta = save.read_meta()?;
+    meta.format = 2;
+    meta.chunk_size = legacy_chunk_size();
+    save.write_meta(&meta)
+}
+
+fn share_chunks(save: &WorldSave) -> io::Result<()> {
+    save.move_chunks_to_objects()?;
+    let mut meta = save.read_meta()?;
+    meta.format = 3;
+    save.write_meta(&meta)
+}
+
+pub fn migrate(save: &WorldSave) -> Result<(), OpenWorldError> {
+    let format = save.read_meta_as::<FormatHeader>()?.format;
+    if format > WORLD_FORMAT {
+        return Err(OpenWorldError::NewerFormat(format));
+    }
+
+    for migration in MIGRATIONS
+        .iter()
+        .filter(|migration| migration.from >= format)
+    {
+        info!(
+            "Migrating world from format {} to {}",
+            migration.from,
+            migration.from + 1
+        );
+        (migration.migrate)(save)?;
+    }
+
+    let chunk_size = save.read_meta()?.chunk_size;
+    if chunk_size != CHUNK_SIZE as u32 {
+        return Err(OpenWorldError::ChunkSize(chunk_size));
+    }
+
+    Ok(())
+}
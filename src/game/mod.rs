@@ -1,8 +1,16 @@
+mod cache;
 mod cam;
 mod chunk;
+mod codec;
+mod gen;
 mod map;
+mod migrate;
+mod model;
+mod noise;
 mod player;
 mod render;
+mod save;
+mod stage;
 mod vox;
 mod vox_buffer;
 
@@ -10,10 +18,17 @@ use bevy::prelude::*;
 
 use crate::state::GameState;
 
+pub use self::{
+    gen::{GeneratorName, WorldGenerators},
+    save::{now, CurrentWorld, WorldSave},
+};
+
 use self::{
     cam::CamPlugin,
-    chunk::ChunkPlugin,
-    map::{Map, MapPlugin},
+    chunk::{Chunk, ChunkPlugin},
+    gen::GenPlugin,
+    map::{Map, MapPlugin, WorldSeed},
+    model::ModelPlugin,
     player::PlayerPlugin,
     render::RenderPlugin,
 };
@@ -24,7 +39,9 @@ impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(CamPlugin)
             .add_plugin(ChunkPlugin)
+            .add_plugin(GenPlugin)
             .add_plugin(MapPlugin)
+            .add_plugin(ModelPlugin)
             .add_plugin(PlayerPlugin)
             .add_plugin(RenderPlugin)
             .init_resource::<DespawnQueue>()
@@ -45,15 +62,30 @@ fn despawn(mut commands: Commands, mut despawn_queue: ResMut<DespawnQueue>) {
 fn exit_game(
     mut commands: Commands,
     chunks: Query<Entity>,
+    mut saved_chunks: Query<&mut Chunk>,
+    mut map: ResMut<Map>,
+    mut world: ResMut<CurrentWorld>,
     keys: Res<Input<KeyCode>>,
     mut state: ResMut<State<GameState>>,
 ) {
     if keys.just_pressed(KeyCode::Escape) {
+        world.meta.last_played = now();
+        if let Err(err) = map.save(&mut saved_chunks, &world.meta) {
+            warn!(
+                "Failed to save world, staying in it so no edits are lost: {}",
+                err
+            );
+            return;
+        }
+
         for chunk_e in chunks.iter() {
             commands.entity(chunk_e).despawn();
         }
 
         commands.remove_resource::<Map>();
+        commands.remove_resource::<WorldSeed>();
+        commands.remove_resource::<GeneratorName>();
+        commands.remove_resource::<CurrentWorld>();
 
         state.set(GameState::MainMenu).unwrap();
     }
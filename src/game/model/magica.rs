@@ -0,0 +1,808 @@
+use std::iter::once;
+
+use bevy::{
+    math::const_ivec3,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+
+use crate::game::vox::Vox;
+
+use super::{rgb, ModelError, Orientation, VoxModel};
+
+const MAGIC: &[u8] = b"VOX ";
+const VERSION: i32 = 150;
+const MAX_MODEL_SIZE: i32 = 256;
+const MAX_COLORS: usize = 255;
+const MAX_DEPTH: usize = 64;
+// Groups can list the same child many times, so the nodes visited and the voxes they add up to
+// are capped too
+const MAX_VISITS: usize = 1 << 16;
+const MAX_VOXES: usize = 1 << 24;
+// Far beyond any real scene, and small enough that models' sizes can't overflow
+const MAX_TRANSLATION: i32 = 1 << 24;
+
+// MagicaVoxel is Z-up, so its Y axis points away from the viewer and becomes our -Z
+const Y_UP: Orientation = Orientation {
+    cols: [
+        const_ivec3!([1, 0, 0]),
+        const_ivec3!([0, 0, -1]),
+        const_ivec3!([0, 1, 0]),
+    ],
+};
+
+type Dict = HashMap<String, String>;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ModelError> {
+        if len > self.bytes.len() {
+            return Err(ModelError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> Result<i32, ModelError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, ModelError> {
+        usize::try_from(self.i32()?).map_err(|_| ModelError::Invalid("negative length"))
+    }
+
+    fn string(&mut self) -> Result<String, ModelError> {
+        let len = self.len()?;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> Result<Dict, ModelError> {
+        (0..self.len()?)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+
+    // A chunk's ID, content and children
+    fn chunk(&mut self) -> Result<(&'a [u8], Reader<'a>, Reader<'a>), ModelError> {
+        let id = self.take(4)?;
+        let content_len = self.len()?;
+        let children_len = self.len()?;
+        Ok((
+            id,
+            Reader {
+                bytes: self.take(content_len)?,
+            },
+            Reader {
+                bytes: self.take(children_len)?,
+            },
+        ))
+    }
+}
+
+enum Node {
+    Transform {
+        child: i32,
+        hidden: bool,
+        layer: i32,
+        orientation: Orientation,
+        translation: IVec3,
+    },
+    Group(Vec<i32>),
+    Shape(Option<usize>),
+}
+
+struct Shape {
+    size: IVec3,
+    voxes: Vec<(IVec3, u8)>,
+}
+
+// MagicaVoxel's default palette is a color cube without black, then red, green, blue and gray
+// ramps
+const CUBE_LEVELS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+const RAMP_LEVELS: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+fn default_palette() -> Vec<Color> {
+    let cube = CUBE_LEVELS
+        .into_iter()
+        .flat_map(|r| {
+            CUBE_LEVELS
+                .into_iter()
+                .flat_map(move |g| CUBE_LEVELS.into_iter().map(move |b| [r, g, b]))
+        })
+        .filter(|rgb| *rgb != [0, 0, 0]);
+    let ramps = [[1, 0, 0], [0, 1, 0], [0, 0, 1], [1, 1, 1]]
+        .into_iter()
+        .flat_map(|mask: [u8; 3]| {
+            RAMP_LEVELS
+                .into_iter()
+                .map(move |level| mask.map(|m| m * level))
+        });
+
+    once([0, 0, 0])
+        .chain(cube)
+        .chain(ramps)
+        .map(|[r, g, b]| Color::rgb_u8(r, g, b))
+        .collect()
+}
+
+// Each row of the rotation has one nonzero entry: bits 0 to 3 give its column for the first two
+// rows, and bits 4 to 6 whether each row is negative
+fn rotation(byte: u8) -> Result<Orientation, ModelError> {
+    let first = (byte & 3) as usize;
+    let second = (byte >> 2 & 3) as usize;
+    if first > 2 || second > 2 || first == second {
+        return Err(ModelError::Invalid("invalid rotation"));
+    }
+
+    let mut cols = [[0; 3]; 3];
+    for (row, col) in [first, second, 3 - first - second].into_iter().enumerate() {
+        cols[col][row] = if byte >> (4 + row) & 1 == 1 { -1 } else { 1 };
+    }
+
+    Ok(Orientation::new(cols.map(IVec3::from)))
+}
+
+fn translation(value: &str) -> Result<IVec3, ModelError> {
+    let coords = value
+        .split_whitespace()
+        .map(|coord| {
+            coord
+                .parse()
+                .map_err(|_| ModelError::Invalid("invalid translation"))
+        })
+        .collect::<Result<Vec<i32>, _>>()?;
+    match coords[..] {
+        [x, y, z] => translate(IVec3::ZERO, IVec3::new(x, y, z)),
+        _ => Err(ModelError::Invalid("invalid translation")),
+    }
+}
+
+// Translations add up through the scene graph, so the total is checked at each step
+fn translate(translation: IVec3, offset: IVec3) -> Result<IVec3, ModelError> {
+    let coords = [0, 1, 2].map(|axis| {
+        translation[axis]
+            .checked_add(offset[axis])
+            .filter(|coord| (-MAX_TRANSLATION..=MAX_TRANSLATION).contains(coord))
+    });
+    match coords {
+        [Some(x), Some(y), Some(z)] => Ok(IVec3::new(x, y, z)),
+        _ => Err(ModelError::Invalid("translation out of range")),
+    }
+}
+
+fn read_transform(content: &mut Reader) -> Result<(i32, Node), ModelError> {
+    let id = content.i32()?;
+    let attributes = content.dict()?;
+    let child = content.i32()?;
+    content.i32()?;
+    let layer = content.i32()?;
+    let frame = match content.len()? {
+        0 => Dict::default(),
+        _ => content.dict()?,
+    };
+
+    Ok((
+        id,
+        Node::Transform {
+            child,
+            hidden: attributes
+                .get("_hidden")
+                .is_some_and(|hidden| hidden == "1"),
+            layer,
+            orientation: frame
+                .get("_r")
+                .map(|r| {
+                    rotation(
+                        r.parse()
+                            .map_err(|_| ModelError::Invalid("invalid rotation"))?,
+                    )
+                })
+                .transpose()?
+                .unwrap_or(Orientation::IDENTITY),
+            translation: frame
+                .get("_t")
+                .map(|t| translation(t))
+                .transpose()?
+                .unwrap_or_default(),
+        },
+    ))
+}
+
+struct Scene {
+    nodes: HashMap<i32, Node>,
+    hidden_layers: HashSet<i32>,
+}
+
+impl Scene {
+    // Collects each visible shape's model with its accumulated orientation and translation
+    fn instances(
+        &self,
+        id: i32,
+        orientation: Orientation,
+        translation: IVec3,
+        depth: usize,
+        visits: &mut usize,
+        instances: &mut Vec<(usize, Orientation, IVec3)>,
+    ) -> Result<(), ModelError> {
+        if depth > MAX_DEPTH {
+            return Err(ModelError::Invalid("scene graph is too deep"));
+        }
+        *visits += 1;
+        if *visits > MAX_VISITS {
+            return Err(ModelError::Invalid("scene graph is too large"));
+        }
+
+        match self.nodes.get(&id) {
+            Some(Node::Transform {
+                child,
+                hidden,
+                layer,
+                orientation: local_orientation,
+                translation: local_translation,
+            }) => {
+                if !hidden && !self.hidden_layers.contains(layer) {
+                    self.instances(
+                        *child,
+                        orientation.then(*local_orientation),
+                        translate(translation, orientation.apply(*local_translation))?,
+                        depth + 1,
+                        visits,
+                        instances,
+                    )?;
+                }
+            }
+            Some(Node::Group(children)) => {
+                for child in children {
+                    self.instances(
+                        *child,
+                        orientation,
+                        translation,
+                        depth + 1,
+                        visits,
+                        instances,
+                    )?;
+                }
+            }
+            Some(Node::Shape(model)) => {
+                if let Some(model) = model {
+                    instances.push((*model, orientation, translation));
+                }
+            }
+            None => return Err(ModelError::Invalid("missing scene node")),
+        }
+
+        Ok(())
+    }
+}
+
+fn halve(pos: IVec3) -> IVec3 {
+    IVec3::new(
+        pos.x.div_euclid(2),
+        pos.y.div_euclid(2),
+        pos.z.div_euclid(2),
+    )
+}
+
+pub fn read(bytes: &[u8]) -> Result<VoxModel, ModelError> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(ModelError::Invalid("missing VOX header"));
+    }
+    reader.i32()?;
+
+    let (id, _, mut children) = reader.chunk()?;
+    if id != b"MAIN" {
+        return Err(ModelError::Invalid("missing MAIN chunk"));
+    }
+
+    let mut sizes = Vec::new();
+    let mut shapes = Vec::new();
+    let mut palette = default_palette();
+    let mut scene = Scene {
+        nodes: default(),
+        hidden_layers: default(),
+    };
+
+    while !children.bytes.is_empty() {
+        let (id, mut content, _) = children.chunk()?;
+        match id {
+            b"SIZE" => {
+                let size = IVec3::new(content.i32()?, content.i32()?, content.i32()?);
+                if size.cmplt(IVec3::ONE).any() || size.cmpgt(IVec3::splat(MAX_MODEL_SIZE)).any() {
+                    return Err(ModelError::Invalid("invalid model size"));
+                }
+                sizes.push(size);
+            }
+            b"XYZI" => {
+                let size = *sizes
+                    .get(shapes.len())
+                    .ok_or(ModelError::Invalid("XYZI chunk without a SIZE chunk"))?;
+                let voxes = (0..content.len()?)
+                    .map(|_| {
+                        let vox = content.take(4)?;
+                        Ok((
+                            IVec3::new(vox[0] as i32, vox[1] as i32, vox[2] as i32),
+                            vox[3],
+                        ))
+                    })
+                    .collect::<Result<_, ModelError>>()?;
+                shapes.push(Shape { size, voxes });
+            }
+            // The palette is offset by one, since index 0 is empty
+            b"RGBA" => {
+                for color in &mut palette[1..] {
+                    let rgba = content.take(4)?;
+                    *color = Color::rgb_u8(rgba[0], rgba[1], rgba[2]);
+                }
+            }
+            b"nTRN" => {
+                let (id, node) = read_transform(&mut content)?;
+                scene.nodes.insert(id, node);
+            }
+            b"nGRP" => {
+                let id = content.i32()?;
+                content.dict()?;
+                let children = (0..content.len()?)
+                    .map(|_| content.i32())
+                    .collect::<Result<_, _>>()?;
+                scene.nodes.insert(id, Node::Group(children));
+            }
+            // Shapes with several models are animations, so only their first frame is used
+            b"nSHP" => {
+                let id = content.i32()?;
+                content.dict()?;
+                let model = match content.len()? {
+                    0 => None,
+                    _ => Some(content.len()?),
+                };
+                scene.nodes.insert(id, Node::Shape(model));
+            }
+            b"LAYR" => {
+                let id = content.i32()?;
+                if content
+                    .dict()?
+                    .get("_hidden")
+                    .is_some_and(|hidden| hidden == "1")
+                {
+                    scene.hidden_layers.insert(id);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Files without a scene graph stack their models at the origin
+    let mut instances = Vec::new();
+    if scene.nodes.is_empty() {
+        instances
+            .extend((0..shapes.len()).map(|model| (model, Orientation::IDENTITY, IVec3::ZERO)));
+    } else {
+        scene.instances(
+            0,
+            Orientation::IDENTITY,
+            IVec3::ZERO,
+            0,
+            &mut 0,
+            &mut instances,
+        )?;
+    }
+
+    let mut voxes = Vec::new();
+    for (model, orientation, translation) in instances {
+        let shape = shapes
+            .get(model)
+            .ok_or(ModelError::Invalid("missing model"))?;
+        if voxes.len() + shape.voxes.len() > MAX_VOXES {
+            return Err(ModelError::Invalid("scene has too many voxes"));
+        }
+
+        // Models are centered on their translation, rounding the way MagicaVoxel does when
+        // they're rotated
+        voxes.extend(
+            shape
+                .voxes
+                .iter()
+                .filter(|(_, index)| *index != 0)
+                .map(|(pos, index)| {
+                    (
+                        Y_UP.apply(
+                            halve(orientation.apply(*pos * 2 + IVec3::ONE - shape.size))
+                                + translation,
+                        ),
+                        Vox::solid(palette[*index as usize]),
+                    )
+                }),
+        );
+    }
+
+    Ok(VoxModel::new(voxes))
+}
+
+fn chunk(id: &[u8], content: &[u8], children: &[u8]) -> Vec<u8> {
+    let mut bytes = id.to_vec();
+    bytes.extend((content.len() as i32).to_le_bytes());
+    bytes.extend((children.len() as i32).to_le_bytes());
+    bytes.extend(content);
+    bytes.extend(children);
+    bytes
+}
+
+fn ints(ints: &[i32]) -> Vec<u8> {
+    ints.iter().flat_map(|int| int.to_le_bytes()).collect()
+}
+
+fn dict(pairs: &[(&str, &str)]) -> Vec<u8> {
+    let mut bytes = ints(&[pairs.len() as i32]);
+    for string in pairs.iter().flat_map(|(key, value)| [key, value]) {
+        bytes.extend(ints(&[string.len() as i32]));
+        bytes.extend(string.as_bytes());
+    }
+    bytes
+}
+
+fn transform(id: i32, child: i32, layer: i32, frame: &[(&str, &str)]) -> Vec<u8> {
+    let content = [
+        ints(&[id]),
+        dict(&[]),
+        ints(&[child, -1, layer, 1]),
+        dict(frame),
+    ]
+    .concat();
+    chunk(b"nTRN", &content, &[])
+}
+
+fn shape(id: i32, model: i32) -> Vec<u8> {
+    let content = [ints(&[id]), dict(&[]), ints(&[1, model]), dict(&[])].concat();
+    chunk(b"nSHP", &content, &[])
+}
+
+// Median cut: keeps splitting the box of colors with the widest channel at its weighted median,
+// so palettes with few enough colors come out exact. Returns the palette and each color's index.
+fn quantize(counts: HashMap<[u8; 3], usize>) -> (Vec<[u8; 3]>, HashMap<[u8; 3], u8>) {
+    let mut boxes = vec![counts.into_iter().collect::<Vec<_>>()];
+    boxes.retain(|colors| !colors.is_empty());
+    while boxes.len() < MAX_COLORS {
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .flat_map(|(i, colors)| {
+                (0..3).map(move |channel| {
+                    let values = colors.iter().map(|(color, _)| color[channel]);
+                    (
+                        i,
+                        channel,
+                        values.clone().max().unwrap() - values.min().unwrap(),
+                    )
+                })
+            })
+            .max_by_key(|(_, _, range)| *range);
+        let (i, channel) = match widest {
+            Some((i, channel, _)) => (i, channel),
+            None => break,
+        };
+
+        let mut colors = boxes.swap_remove(i);
+        colors.sort_unstable_by_key(|(color, _)| (color[channel], *color));
+        let total = colors.iter().map(|(_, count)| count).sum::<usize>();
+        let mut below = 0;
+        let median = colors
+            .iter()
+            .position(|(_, count)| {
+                below += count;
+                below * 2 >= total
+            })
+            .unwrap();
+        // Equal values stay on the same side, so the split always narrows the box
+        let median = colors[median].0[channel];
+        let split = colors
+            .iter()
+            .position(|(color, _)| color[channel] > median)
+            .or_else(|| {
+                colors
+                    .iter()
+                    .position(|(color, _)| color[channel] == median)
+            })
+            .unwrap();
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    let palette = boxes
+        .iter()
+        .map(|colors| {
+            let total = colors.iter().map(|(_, count)| count).sum::<usize>();
+            [0, 1, 2].map(|channel| {
+                let sum = colors
+                    .iter()
+                    .map(|(color, count)| color[channel] as usize * count)
+                    .sum::<usize>();
+                ((sum + total / 2) / total) as u8
+            })
+        })
+        .collect();
+    let indices = boxes
+        .iter()
+        .enumerate()
+        .flat_map(|(i, colors)| colors.iter().map(move |(color, _)| (*color, i as u8 + 1)))
+        .collect();
+
+    (palette, indices)
+}
+
+// Splits the model into as many models as the size limit needs, in a group whose origin is the
+// model's minimum corner
+pub fn write(model: &VoxModel) -> Vec<u8> {
+    let mut counts = HashMap::default();
+    for (_, vox) in &model.voxes {
+        *counts.entry(rgb(vox.color)).or_default() += 1;
+    }
+    let (palette, indices) = quantize(counts);
+
+    // Undoes `Y_UP`, keeping positions from the minimum corner
+    let size = IVec3::new(model.size.x, model.size.z, model.size.y).max(IVec3::ZERO);
+    let tile_counts = (size + IVec3::splat(MAX_MODEL_SIZE - 1)) / MAX_MODEL_SIZE;
+    let mut tiles = HashMap::<IVec3, Vec<u8>>::default();
+    for (pos, vox) in &model.voxes {
+        let pos = IVec3::new(pos.x, size.y - 1 - pos.z, pos.y);
+        let tile = pos / MAX_MODEL_SIZE;
+        let local = pos - tile * MAX_MODEL_SIZE;
+        tiles.entry(tile).or_default().extend([
+            local.x as u8,
+            local.y as u8,
+            local.z as u8,
+            indices[&rgb(vox.color)],
+        ]);
+    }
+
+    let mut models = Vec::new();
+    let mut nodes = Vec::new();
+    let mut group = Vec::new();
+    for x in 0..tile_counts.x {
+        for y in 0..tile_counts.y {
+            for z in 0..tile_counts.z {
+                let tile = IVec3::new(x, y, z);
+                let offset = tile * MAX_MODEL_SIZE;
+                let tile_size = (size - offset).min(IVec3::splat(MAX_MODEL_SIZE));
+                let voxes = tiles.remove(&tile).unwrap_or_default();
+
+                let model_id = (models.len() / 2) as i32;
+                models.push(chunk(b"SIZE", &ints(&tile_size.to_array()), &[]));
+                models.push(chunk(
+                    b"XYZI",
+                    &[ints(&[voxes.len() as i32 / 4]), voxes].concat(),
+                    &[],
+                ));
+
+                // Reading centers each model on its translation
+                let center = offset + tile_size / 2;
+                let translation = format!("{} {} {}", center.x, center.y, center.z);
+                let node_id = 2 + model_id * 2;
+                group.push(node_id);
+                nodes.push(transform(node_id, node_id + 1, 0, &[("_t", &translation)]));
+                nodes.push(shape(node_id + 1, model_id));
+            }
+        }
+    }
+
+    let mut rgba = palette
+        .iter()
+        .flat_map(|[r, g, b]| [*r, *g, *b, 0xff])
+        .collect::<Vec<_>>();
+    rgba.resize(256 * 4, 0);
+
+    let children = [
+        models.concat(),
+        transform(0, 1, -1, &[]),
+        chunk(
+            b"nGRP",
+            &[
+                ints(&[1]),
+                dict(&[]),
+                ints(&[group.len() as i32]),
+                ints(&group),
+            ]
+            .concat(),
+            &[],
+        ),
+        nodes.concat(),
+        chunk(b"RGBA", &rgba, &[]),
+    ]
+    .concat();
+
+    [
+        MAGIC.to_vec(),
+        ints(&[VERSION]),
+        chunk(b"MAIN", &[], &children),
+    ]
+    .concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::model::{assert_rejects_truncated, sorted};
+
+    // A 2x1x1 model at the origin and a 1x1x3 model moved 10 along X
+    fn scene() -> Vec<u8> {
+        let children = [
+            chunk(b"SIZE", &ints(&[2, 1, 1]), &[]),
+            chunk(
+                b"XYZI",
+                &[ints(&[2]), vec![0, 0, 0, 1, 1, 0, 0, 2]].concat(),
+                &[],
+            ),
+            chunk(b"SIZE", &ints(&[1, 1, 3]), &[]),
+            chunk(
+                b"XYZI",
+                &[ints(&[3]), vec![0, 0, 0, 3, 0, 0, 1, 3, 0, 0, 2, 3]].concat(),
+                &[],
+            ),
+            transform(0, 1, -1, &[]),
+            chunk(
+                b"nGRP",
+                &[ints(&[1]), dict(&[]), ints(&[2, 2, 4])].concat(),
+                &[],
+            ),
+            transform(2, 3, 0, &[]),
+            shape(3, 0),
+            transform(4, 5, 0, &[("_t", "10 0 0")]),
+            shape(5, 1),
+        ]
+        .concat();
+
+        [
+            MAGIC.to_vec(),
+            ints(&[VERSION]),
+            chunk(b"MAIN", &[], &children),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn reads_scene_graph() {
+        let model = read(&scene()).unwrap();
+        let mut voxes = model
+            .voxes
+            .iter()
+            .map(|(pos, vox)| (pos.to_array(), vox.color.as_rgba_u32()))
+            .collect::<Vec<_>>();
+        voxes.sort_unstable();
+
+        let white = Color::WHITE.as_rgba_u32();
+        let yellow = Color::rgb_u8(0xff, 0xff, 0xcc).as_rgba_u32();
+        let cube = Color::rgb_u8(0xff, 0xff, 0x99).as_rgba_u32();
+        assert_eq!(model.size, IVec3::new(12, 3, 1));
+        assert_eq!(
+            voxes,
+            [
+                ([0, 1, 0], white),
+                ([1, 1, 0], yellow),
+                ([11, 0, 0], cube),
+                ([11, 1, 0], cube),
+                ([11, 2, 0], cube),
+            ]
+        );
+    }
+
+    // Groups listing the same transform again and again, nested until the shape at the bottom
+    // would be instanced 16^5 times
+    #[test]
+    fn rejects_repeated_children() {
+        let mut children = vec![
+            chunk(b"SIZE", &ints(&[1, 1, 1]), &[]),
+            chunk(b"XYZI", &[ints(&[1]), vec![0, 0, 0, 1]].concat(), &[]),
+        ];
+        for level in 0..5 {
+            let (transform_id, group_id) = (level * 2, level * 2 + 1);
+            children.push(transform(transform_id, group_id, -1, &[]));
+            children.push(chunk(
+                b"nGRP",
+                &[
+                    ints(&[group_id]),
+                    dict(&[]),
+                    ints(&[16]),
+                    ints(&[group_id + 1; 16]),
+                ]
+                .concat(),
+                &[],
+            ));
+        }
+        children.push(transform(10, 11, -1, &[]));
+        children.push(shape(11, 0));
+
+        let bytes = [
+            MAGIC.to_vec(),
+            ints(&[VERSION]),
+            chunk(b"MAIN", &[], &children.concat()),
+        ]
+        .concat();
+        assert!(read(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_translations_out_of_range() {
+        assert_eq!(translation("1 -2 3").unwrap(), IVec3::new(1, -2, 3));
+        assert!(translation("-2147483648 0 0").is_err());
+        assert!(translate(IVec3::X * MAX_TRANSLATION, IVec3::X).is_err());
+        assert!(translate(IVec3::X * i32::MAX, IVec3::X).is_err());
+    }
+
+    #[test]
+    fn decodes_rotations() {
+        assert_eq!(rotation(4).unwrap(), Orientation::IDENTITY);
+        assert_eq!(
+            rotation(17).unwrap().apply(IVec3::new(1, 2, 3)),
+            IVec3::new(-2, 1, 3)
+        );
+        assert!(rotation(0).is_err());
+        assert_eq!(default_palette().len(), 256);
+    }
+
+    #[test]
+    fn round_trips_large_models() {
+        let model = VoxModel::new(
+            [
+                IVec3::new(0, 0, 0),
+                IVec3::new(299, 3, 1),
+                IVec3::new(5, 260, 2),
+                IVec3::new(1, 2, 300),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, pos)| (pos, Vox::solid(Color::rgb_u8(i as u8 * 60, 10, 200))))
+            .collect(),
+        );
+
+        let read = read(&write(&model)).unwrap();
+        assert_eq!(read.size, model.size);
+        assert_eq!(
+            sorted(&read, |vox| rgb(vox.color)),
+            sorted(&model, |vox| rgb(vox.color))
+        );
+    }
+
+    #[test]
+    fn quantizes_large_palettes() {
+        let model = VoxModel::new(
+            (0..1000)
+                .map(|i| {
+                    let color = Color::rgb_u8(
+                        (i % 10 * 25) as u8,
+                        (i / 10 % 10 * 25) as u8,
+                        (i / 100 * 25) as u8,
+                    );
+                    (IVec3::new(i, 0, 0), Vox::solid(color))
+                })
+                .collect(),
+        );
+
+        let read = read(&write(&model)).unwrap();
+        let colors = read
+            .voxes
+            .iter()
+            .map(|(_, vox)| rgb(vox.color))
+            .collect::<HashSet<_>>();
+        assert_eq!(read.voxes.len(), 1000);
+        assert!(colors.len() <= MAX_COLORS);
+        for ((_, original), (_, quantized)) in sorted(&model, |vox| rgb(vox.color))
+            .into_iter()
+            .zip(sorted(&read, |vox| rgb(vox.color)))
+        {
+            for (a, b) in original.into_iter().zip(quantized) {
+                assert!((a as i32 - b as i32).abs() <= 25);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        assert_rejects_truncated(&scene(), read);
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::game::vox::Vox;
+
+use super::VoxModel;
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const JSON_CHUNK: &[u8] = b"JSON";
+const BIN_CHUNK: &[u8] = b"BIN\0";
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+type ColorKey = [u32; 4];
+
+fn color_key(vox: &Vox) -> ColorKey {
+    vox.color.as_rgba_f32().map(f32::to_bits)
+}
+
+// The visible faces of a model, with coplanar faces of the same color merged into quads. Each
+// quad has its own four vertices, counterclockwise from outside.
+#[derive(Default)]
+pub struct Surface {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    colors: Vec<Color>,
+}
+
+impl Surface {
+    pub fn new(model: &VoxModel) -> Self {
+        let size = model.size.max(IVec3::ZERO);
+        let index = |pos: IVec3| (pos.x + pos.y * size.x + pos.z * size.x * size.y) as usize;
+        let mut grid = vec![None; (size.x * size.y * size.z) as usize];
+        for (pos, vox) in &model.voxes {
+            grid[index(*pos)] = Some(vox);
+        }
+        let get = |pos: IVec3| {
+            (pos.cmpge(IVec3::ZERO).all() && pos.cmplt(size).all())
+                .then(|| grid[index(pos)])
+                .flatten()
+        };
+
+        let mut surface = Self::default();
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            for sign in [1, -1] {
+                let mut normal = IVec3::ZERO;
+                normal[axis] = sign;
+
+                for layer in 0..size[axis] {
+                    // Which face each vox in this layer shows in the normal's direction, if any
+                    let mut mask = Vec::with_capacity((size[u] * size[v]) as usize);
+                    for b in 0..size[v] {
+                        for a in 0..size[u] {
+                            let mut pos = IVec3::ZERO;
+                            pos[axis] = layer;
+                            pos[u] = a;
+                            pos[v] = b;
+
+                            let face = get(pos).filter(|vox| {
+                                !get(pos + normal)
+                                    .is_some_and(|neighbor| neighbor.material.hides(vox.material))
+                            });
+                            mask.push(face);
+                        }
+                    }
+
+                    surface.merge_faces(
+                        &mut mask,
+                        (size[u], size[v]),
+                        |a, b| {
+                            let mut pos = Vec3::ZERO;
+                            pos[axis] = (layer + (sign > 0) as i32) as f32;
+                            pos[u] = a as f32;
+                            pos[v] = b as f32;
+                            pos
+                        },
+                        normal,
+                        sign < 0,
+                    );
+                }
+            }
+        }
+
+        surface
+    }
+
+    // Greedily grows each face along the first axis, then the second, while the color matches
+    fn merge_faces(
+        &mut self,
+        mask: &mut [Option<&Vox>],
+        (width, height): (i32, i32),
+        corner: impl Fn(i32, i32) -> Vec3,
+        normal: IVec3,
+        flip: bool,
+    ) {
+        let at = |a: i32, b: i32| (a + b * width) as usize;
+        for b in 0..height {
+            let mut a = 0;
+            while a < width {
+                let vox = match mask[at(a, b)] {
+                    Some(vox) => vox,
+                    None => {
+                        a += 1;
+                        continue;
+                    }
+                };
+
+                let key = color_key(vox);
+                let matches = |mask: &[Option<&Vox>], a, b| {
+                    mask[at(a, b)].is_some_and(|other| color_key(other) == key)
+                };
+                let mut quad_width = 1;
+                while a + quad_width < width && matches(mask, a + quad_width, b) {
+                    quad_width += 1;
+                }
+                let mut quad_height = 1;
+                while b + quad_height < height
+                    && (a..a + quad_width).all(|a| matches(mask, a, b + quad_height))
+                {
+                    quad_height += 1;
+                }
+
+                for b in b..b + quad_height {
+                    for a in a..a + quad_width {
+                        mask[at(a, b)] = None;
+                    }
+                }
+
+                let mut corners = [
+                    corner(a, b),
+                    corner(a + quad_width, b),
+                    corner(a + quad_width, b + quad_height),
+                    corner(a, b + quad_height),
+                ];
+                if flip {
+                    corners.reverse();
+                }
+                self.positions
+                    .extend(corners.map(|corner| corner.to_array()));
+                self.normals.extend([normal.as_vec3().to_array(); 4]);
+                self.colors.extend([vox.color; 4]);
+                a += quad_width;
+            }
+        }
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.positions.len() / 4
+    }
+
+    fn indices(&self) -> impl Iterator<Item = u32> {
+        (0..self.quad_count() as u32)
+            .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|corner| quad * 4 + corner))
+    }
+}
+
+fn floats(values: impl IntoIterator<Item = f32>) -> Vec<u8> {
+    values
+        .into_iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+// A binary glTF 2.0 file with a single mesh, colored with linear vertex colors
+pub fn write_glb(surface: &Surface) -> Vec<u8> {
+    let vertex_count = surface.positions.len();
+    let index_count = surface.quad_count() * 6;
+    let views = [
+        floats(surface.positions.iter().flatten().copied()),
+        floats(surface.normals.iter().flatten().copied()),
+        floats(
+            surface
+                .colors
+                .iter()
+                .flat_map(|color| color.as_linear_rgba_f32()),
+        ),
+        surface
+            .indices()
+            .flat_map(|index| index.to_le_bytes())
+            .collect(),
+    ];
+
+    let json = if vertex_count == 0 {
+        r#"{"asset":{"version":"2.0"},"scene":0,"scenes":[{"nodes":[]}]}"#.to_owned()
+    } else {
+        let (min, max) = surface.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), pos| (min.min(Vec3::from(*pos)), max.max(Vec3::from(*pos))),
+        );
+
+        let mut offset = 0;
+        let mut buffer_views = Vec::new();
+        for (i, view) in views.iter().enumerate() {
+            let target = if i == 3 {
+                ELEMENT_ARRAY_BUFFER
+            } else {
+                ARRAY_BUFFER
+            };
+            buffer_views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+                offset,
+                view.len(),
+                target
+            ));
+            offset += view.len();
+        }
+
+        format!(
+            concat!(
+                r#"{{"asset":{{"version":"2.0","generator":"voxmod"}},"scene":0,"#,
+                r#""scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+                r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"#,
+                r#""COLOR_0":2}},"indices":3,"mode":4}}]}}],"#,
+                r#""accessors":[{{"bufferView":0,"componentType":{float},"count":{vertices},"#,
+                r#""type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}},"#,
+                r#"{{"bufferView":1,"componentType":{float},"count":{vertices},"type":"VEC3"}},"#,
+                r#"{{"bufferView":2,"componentType":{float},"count":{vertices},"type":"VEC4"}},"#,
+                r#"{{"bufferView":3,"componentType":{uint},"count":{indices},"type":"SCALAR"}}],"#,
+                r#""bufferViews":[{views}],"buffers":[{{"byteLength":{len}}}]}}"#,
+            ),
+            min.x,
+            min.y,
+            min.z,
+            max.x,
+            max.y,
+            max.z,
+            float = FLOAT,
+            uint = UNSIGNED_INT,
+            vertices = vertex_count,
+            indices = index_count,
+            views = buffer_views.join(","),
+            len = offset,
+        )
+    };
+
+    let mut json = json.into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let bin = views.concat();
+
+    let len = 12 + 8 + json.len() + if bin.is_empty() { 0 } else { 8 + bin.len() };
+    let mut bytes = GLB_MAGIC.to_vec();
+    bytes.extend(GLB_VERSION.to_le_bytes());
+    bytes.extend((len as u32).to_le_bytes());
+    for (id, data) in [(JSON_CHUNK, json), (BIN_CHUNK, bin)] {
+        if !data.is_empty() {
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(id);
+            bytes.extend(data);
+        }
+    }
+
+    bytes
+}
+
+// Wavefront OBJ, with sRGB vertex colors after each position as most tools expect
+pub fn write_obj(surface: &Surface) -> String {
+    let mut obj = "# voxmod\n".to_owned();
+    for (pos, color) in surface.positions.iter().zip(&surface.colors) {
+        let [r, g, b, _] = color.as_rgba_f32();
+        writeln!(obj, "v {} {} {} {} {} {}", pos[0], pos[1], pos[2], r, g, b).unwrap();
+    }
+    for normal in surface.normals.iter().step_by(4) {
+        writeln!(obj, "vn {} {} {}", normal[0], normal[1], normal[2]).unwrap();
+    }
+    for quad in 0..surface.quad_count() {
+        let [a, b, c, d] = [1, 2, 3, 4].map(|corner| quad * 4 + corner);
+        let n = quad + 1;
+        writeln!(obj, "f {a}//{n} {b}//{n} {c}//{n} {d}//{n}").unwrap();
+    }
+
+    obj
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(colors: &[Color]) -> VoxModel {
+        VoxModel::new(
+            colors
+                .iter()
+                .enumerate()
+                .map(|(x, color)| (IVec3::new(x as i32, 0, 0), Vox::solid(*color)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn merges_faces_of_the_same_color() {
+        assert_eq!(Surface::new(&model(&[Color::RED])).quad_count(), 6);
+        assert_eq!(Surface::new(&model(&[Color::RED; 5])).quad_count(), 6);
+        // The faces between the voxes are hidden, and the long sides split by color
+        assert_eq!(
+            Surface::new(&model(&[Color::RED, Color::RED, Color::BLUE])).quad_count(),
+            10
+        );
+    }
+
+    #[test]
+    fn faces_point_outwards() {
+        let surface = Surface::new(&model(&[Color::RED; 3]));
+        let center = Vec3::new(1.5, 0.5, 0.5);
+        for quad in 0..surface.quad_count() {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(surface.positions[quad * 4 + i]));
+            let normal = Vec3::from(surface.normals[quad * 4]);
+            assert_eq!((b - a).cross(c - b).normalize(), normal);
+            assert!((a - center).dot(normal) > 0.);
+        }
+    }
+
+    #[test]
+    fn writes_valid_containers() {
+        let surface = Surface::new(&model(&[Color::RED, Color::BLUE]));
+        let glb = write_glb(&surface);
+        assert_eq!(&glb[..4], GLB_MAGIC);
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        assert_eq!(glb.len() % 4, 0);
+
+        let obj = write_obj(&surface);
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("v ")).count(),
+            40
+        );
+        assert_eq!(
+            obj.lines().filter(|line| line.starts_with("f ")).count(),
+            10
+        );
+
+        let empty = write_glb(&Surface::default());
+        assert_eq!(
+            u32::from_le_bytes(empty[8..12].try_into().unwrap()) as usize,
+            empty.len()
+        );
+    }
+}
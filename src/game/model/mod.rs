@@ -0,0 +1,413 @@
+mod magica;
+mod mesh;
+mod nbt;
+mod points;
+mod qubicle;
+mod schematic;
+mod slices;
+mod triangles;
+mod voxelize;
+
+use std::{
+    f32::consts::FRAC_PI_2,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    math::const_ivec3,
+    prelude::*,
+    render::camera::Camera3d,
+    tasks::{AsyncComputeTaskPool, Task},
+};
+use futures_lite::future::{block_on, poll_once};
+use serde::de::DeserializeOwned;
+
+use crate::state::GameState;
+
+use self::{
+    mesh::Surface, points::PointSettings, triangles::TriangleMesh, voxelize::VoxelizeSettings,
+};
+
+use super::{chunk::Chunk, map::Map, save::now, vox::Vox};
+
+pub struct ModelPlugin;
+
+impl Plugin for ModelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExportRegion>().add_system_set(
+            SystemSet::on_update(GameState::Game)
+                .with_system(drop_model)
+                .with_system(place_dropped_models)
+                .with_system(export_hotkeys)
+                .with_system(export_regions),
+        );
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelError {
+    UnsupportedFormat(PathBuf),
+    Truncated,
+    Invalid(&'static str),
+    Io(io::Error),
+    Gltf(gltf::Error),
+    Image(image::ImageError),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(path) => {
+                write!(f, "unsupported model format for {}", path.display())
+            }
+            Self::Truncated => write!(f, "model data is truncated"),
+            Self::Invalid(reason) => write!(f, "invalid model: {}", reason),
+            Self::Io(err) => write!(f, "{}", err),
+            Self::Gltf(err) => write!(f, "{}", err),
+            Self::Image(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for ModelError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<gltf::Error> for ModelError {
+    fn from(err: gltf::Error) -> Self {
+        Self::Gltf(err)
+    }
+}
+
+impl From<image::ImageError> for ModelError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err)
+    }
+}
+
+// Settings from a RON file, or the embedded defaults if it's missing or `validate` rejects it
+pub fn load_ron_or_default<T: DeserializeOwned>(
+    path: &str,
+    default: &str,
+    validate: impl FnOnce(&T) -> Result<(), String>,
+) -> T {
+    fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|settings| ron::from_str(&settings).map_err(|err| err.to_string()))
+        .and_then(|settings| validate(&settings).map(|_| settings))
+        .unwrap_or_else(|err| {
+            warn!("Failed to load {}, using the defaults: {}", path, err);
+            ron::from_str(default).unwrap()
+        })
+}
+
+fn lowercase_extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+// The color's RGB channels as bytes, as most formats store them
+fn rgb(color: Color) -> [u8; 3] {
+    let [r, g, b, _] = color.as_rgba_f32();
+    [r, g, b].map(|channel| (channel.clamp(0., 1.) * 255.).round() as u8)
+}
+
+// A rotation by quarter turns, possibly mirrored, stored as the images of the X, Y and Z axes
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Orientation {
+    cols: [IVec3; 3],
+}
+
+impl Orientation {
+    pub const IDENTITY: Self = Self {
+        cols: [
+            const_ivec3!([1, 0, 0]),
+            const_ivec3!([0, 1, 0]),
+            const_ivec3!([0, 0, 1]),
+        ],
+    };
+
+    pub fn new(cols: [IVec3; 3]) -> Self {
+        Self { cols }
+    }
+
+    // Counterclockwise when looking down, like the camera's yaw
+    pub fn rotate_y(turns: i32) -> Self {
+        let (cos, sin) = [(1, 0), (0, 1), (-1, 0), (0, -1)][turns.rem_euclid(4) as usize];
+        Self::new([IVec3::new(cos, 0, -sin), IVec3::Y, IVec3::new(sin, 0, cos)])
+    }
+
+    pub fn apply(self, pos: IVec3) -> IVec3 {
+        self.cols[0] * pos.x + self.cols[1] * pos.y + self.cols[2] * pos.z
+    }
+
+    // The orientation that applies `other`, then `self`
+    pub fn then(self, other: Self) -> Self {
+        Self::new(other.cols.map(|col| self.apply(col)))
+    }
+}
+
+// Voxes in model space, whose bounds start at the origin and span `size`
+#[derive(Clone, Default)]
+pub struct VoxModel {
+    pub size: IVec3,
+    pub voxes: Vec<(IVec3, Vox)>,
+}
+
+impl VoxModel {
+    // Shifts the voxes so their bounds start at the origin
+    pub fn new(voxes: Vec<(IVec3, Vox)>) -> Self {
+        Self::try_new(voxes).expect("model voxes span too far to size")
+    }
+
+    // Like `new`, for voxes read from files, which may be too far apart for the size to fit
+    pub fn try_new(mut voxes: Vec<(IVec3, Vox)>) -> Result<Self, ModelError> {
+        let min = voxes
+            .iter()
+            .map(|(pos, _)| *pos)
+            .reduce(IVec3::min)
+            .unwrap_or_default();
+        let max = voxes
+            .iter()
+            .map(|(pos, _)| *pos)
+            .reduce(IVec3::max)
+            .unwrap_or_else(|| min - IVec3::ONE);
+
+        let size = [0, 1, 2].map(|axis| {
+            max[axis]
+                .checked_sub(min[axis])
+                .and_then(|span| span.checked_add(1))
+        });
+        let size = match size {
+            [Some(x), Some(y), Some(z)] => IVec3::new(x, y, z),
+            _ => return Err(ModelError::Invalid("voxes span too far")),
+        };
+
+        for (pos, _) in &mut voxes {
+            *pos -= min;
+        }
+
+        Ok(Self { size, voxes })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ModelError> {
+        if path.is_dir() {
+            return slices::read(path);
+        }
+
+        match lowercase_extension(path).as_deref() {
+            Some("vox") => magica::read(&fs::read(path)?),
+            Some("qb") => qubicle::read(&fs::read(path)?),
+            Some("schem" | "schematic") => {
+                schematic::read(&fs::read(path)?, &schematic::BlockTable::load())
+            }
+            Some("ply" | "xyz" | "pts") => points::read(path, &PointSettings::load()),
+            Some("obj" | "stl" | "gltf" | "glb") => Ok(voxelize::voxelize(
+                &TriangleMesh::load(path)?,
+                &VoxelizeSettings::load(),
+            )),
+            _ => Err(ModelError::UnsupportedFormat(path.to_owned())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ModelError> {
+        match lowercase_extension(path).as_deref() {
+            Some("vox") => fs::write(path, magica::write(self))?,
+            Some("glb") => fs::write(path, mesh::write_glb(&Surface::new(self)))?,
+            Some("obj") => fs::write(path, mesh::write_obj(&Surface::new(self)))?,
+            Some("slices") => slices::write(self, path)?,
+            Some("qb") => {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                fs::write(path, qubicle::write(self, &name))?
+            }
+            _ => return Err(ModelError::UnsupportedFormat(path.to_owned())),
+        }
+
+        Ok(())
+    }
+
+    pub fn oriented(&self, orientation: Orientation) -> Self {
+        Self::new(
+            self.voxes
+                .iter()
+                .map(|(pos, vox)| (orientation.apply(*pos), vox.clone()))
+                .collect(),
+        )
+    }
+}
+
+const DROP_DISTANCE: f32 = 16.;
+
+// Models dropped onto the window load on a task, then are placed in front of where the camera
+// was when they were dropped, facing the same way
+#[derive(Component)]
+struct DroppedModel {
+    path: PathBuf,
+    camera: Transform,
+    task: Task<Result<VoxModel, ModelError>>,
+}
+
+fn drop_model(
+    mut commands: Commands,
+    mut drops: EventReader<FileDragAndDrop>,
+    cams: Query<&Transform, With<Camera3d>>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+) {
+    for drop in drops.iter() {
+        let path = match drop {
+            FileDragAndDrop::DroppedFile { path_buf, .. } => path_buf.clone(),
+            _ => continue,
+        };
+
+        let camera = match cams.get_single() {
+            Ok(tf) => *tf,
+            Err(_) => continue,
+        };
+
+        let load_path = path.clone();
+        commands.spawn().insert(DroppedModel {
+            path,
+            camera,
+            task: thread_pool.spawn(async move { VoxModel::load(&load_path) }),
+        });
+    }
+}
+
+fn place_dropped_models(
+    mut commands: Commands,
+    mut dropped: Query<(Entity, &mut DroppedModel)>,
+    mut map: ResMut<Map>,
+) {
+    for (dropped_e, mut dropped) in dropped.iter_mut() {
+        let loaded = match block_on(poll_once(&mut dropped.task)) {
+            Some(loaded) => loaded,
+            None => continue,
+        };
+
+        commands.entity(dropped_e).despawn();
+        let model = match loaded {
+            Ok(model) => model,
+            Err(err) => {
+                warn!("Failed to import {}: {}", dropped.path.display(), err);
+                continue;
+            }
+        };
+
+        let tf = dropped.camera;
+        let forward = -tf.local_z();
+        let yaw = (-forward.x).atan2(-forward.z);
+        let orientation = Orientation::rotate_y((yaw / FRAC_PI_2).round() as i32);
+        let size = orientation.apply(model.size).abs();
+        let target = (tf.translation + forward * DROP_DISTANCE)
+            .floor()
+            .as_ivec3();
+        let pos = target - IVec3::new(size.x / 2, 0, size.z / 2);
+
+        info!(
+            "Imported {} with {} voxes at {}",
+            dropped.path.display(),
+            model.voxes.len(),
+            pos
+        );
+        map.place_model(&model, pos, orientation);
+    }
+}
+
+// Writes the voxes between `min` and `max` inclusive to a file, in the format its extension names
+pub struct ExportRegion {
+    pub min: IVec3,
+    pub max: IVec3,
+    pub path: PathBuf,
+}
+
+const EXPORTS_DIR: &str = "exports";
+const EXPORT_RADIUS: i32 = 32;
+static EXPORT_KEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::F2, "vox"),
+    (KeyCode::F3, "qb"),
+    (KeyCode::F4, "glb"),
+    (KeyCode::F5, "obj"),
+    (KeyCode::F6, "slices"),
+];
+
+// Exports the cube around the camera
+fn export_hotkeys(
+    cams: Query<&Transform, With<Camera3d>>,
+    keys: Res<Input<KeyCode>>,
+    mut exports: EventWriter<ExportRegion>,
+) {
+    let center = match cams.get_single() {
+        Ok(tf) => tf.translation.floor().as_ivec3(),
+        Err(_) => return,
+    };
+
+    for (key, extension) in EXPORT_KEYS {
+        if keys.just_pressed(*key) {
+            exports.send(ExportRegion {
+                min: center - IVec3::splat(EXPORT_RADIUS),
+                max: center + IVec3::splat(EXPORT_RADIUS - 1),
+                path: Path::new(EXPORTS_DIR).join(format!("region-{}.{}", now(), extension)),
+            });
+        }
+    }
+}
+
+fn export_regions(
+    mut exports: EventReader<ExportRegion>,
+    chunks: Query<&Chunk>,
+    thread_pool: Res<AsyncComputeTaskPool>,
+    map: Res<Map>,
+) {
+    for export in exports.iter() {
+        if export.min.cmpgt(export.max).any() {
+            warn!(
+                "Failed to export {}: {} is past {}",
+                export.path.display(),
+                export.min,
+                export.max
+            );
+            continue;
+        }
+
+        let region = map.region(&chunks, export.min, export.max);
+        let path = export.path.clone();
+        thread_pool
+            .spawn(async move {
+                let model = region();
+                let saved = match path.parent() {
+                    Some(dir) => fs::create_dir_all(dir).map_err(ModelError::from),
+                    None => Ok(()),
+                }
+                .and_then(|_| model.save(&path));
+                match saved {
+                    Ok(()) => info!("Exported {} voxes to {}", model.voxes.len(), path.display()),
+                    Err(err) => warn!("Failed to export {}: {}", path.display(), err),
+                }
+            })
+            .detach();
+    }
+}
+
+// Model voxes in a stable order, keyed by what the format keeps of each vox
+#[cfg(test)]
+fn sorted<T: Ord>(model: &VoxModel, key: impl Fn(&Vox) -> T) -> Vec<([i32; 3], T)> {
+    let mut voxes = model
+        .voxes
+        .iter()
+        .map(|(pos, vox)| (pos.to_array(), key(vox)))
+        .collect::<Vec<_>>();
+    voxes.sort_unstable();
+    voxes
+}
+
+// Every prefix of a valid file must fail to read rather than panic
+#[cfg(test)]
+fn assert_rejects_truncated<T>(bytes: &[u8], read: impl Fn(&[u8]) -> Result<T, ModelError>) {
+    for len in 0..bytes.len() {
+        assert!(read(&bytes[..len]).is_err());
+    }
+}
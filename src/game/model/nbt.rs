@@ -0,0 +1,164 @@
+use std::io::Read;
+
+use bevy::utils::HashMap;
+use flate2::read::GzDecoder;
+
+use super::ModelError;
+
+const MAX_LEN: u64 = 256 << 20;
+const MAX_DEPTH: usize = 512;
+
+// Only keeps the payloads schematics need: bytes, shorts and ints are widened to ints
+pub enum Tag {
+    Int(i32),
+    ByteArray(Vec<u8>),
+    Compound(HashMap<String, Tag>),
+    Other,
+}
+
+impl Tag {
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Self::Compound(tags) => tags.get(name),
+            _ => None,
+        }
+    }
+
+    pub fn int(&self) -> Option<i32> {
+        match *self {
+            Self::Int(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::ByteArray(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Self::Compound(tags) => Some(tags),
+            _ => None,
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ModelError> {
+        if len > self.bytes.len() {
+            return Err(ModelError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ModelError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    // Checks the length against what's left, so a corrupt length can't allocate much
+    fn len(&mut self, element_size: usize) -> Result<usize, ModelError> {
+        let len = usize::try_from(i32::from_be_bytes(self.array()?))
+            .map_err(|_| ModelError::Invalid("negative NBT length"))?;
+        if len.saturating_mul(element_size) > self.bytes.len() {
+            return Err(ModelError::Truncated);
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String, ModelError> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn tag(&mut self, id: u8, depth: usize) -> Result<Tag, ModelError> {
+        if depth > MAX_DEPTH {
+            return Err(ModelError::Invalid("NBT is nested too deeply"));
+        }
+
+        Ok(match id {
+            1 => Tag::Int(i8::from_be_bytes(self.array()?) as i32),
+            2 => Tag::Int(i16::from_be_bytes(self.array()?) as i32),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 | 6 => {
+                self.take(8)?;
+                Tag::Other
+            }
+            5 => {
+                self.take(4)?;
+                Tag::Other
+            }
+            7 => {
+                let len = self.len(1)?;
+                Tag::ByteArray(self.take(len)?.to_vec())
+            }
+            8 => {
+                self.string()?;
+                Tag::Other
+            }
+            9 => {
+                let [element_id] = self.array()?;
+                // Lists of empty tags have no payload to bound their length with
+                let len = match element_id {
+                    0 => {
+                        self.len(0)?;
+                        0
+                    }
+                    _ => self.len(1)?,
+                };
+                for _ in 0..len {
+                    self.tag(element_id, depth + 1)?;
+                }
+                Tag::Other
+            }
+            10 => {
+                let mut tags = HashMap::default();
+                loop {
+                    let [id] = self.array()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    tags.insert(name, self.tag(id, depth + 1)?);
+                }
+                Tag::Compound(tags)
+            }
+            11 | 12 => {
+                let element_size = if id == 11 { 4 } else { 8 };
+                let len = self.len(element_size)?;
+                self.take(len * element_size)?;
+                Tag::Other
+            }
+            _ => return Err(ModelError::Invalid("unknown NBT tag")),
+        })
+    }
+}
+
+// Reads the root compound of a possibly gzipped NBT file
+pub fn read(bytes: &[u8]) -> Result<Tag, ModelError> {
+    let mut decompressed = Vec::new();
+    let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(bytes)
+            .take(MAX_LEN)
+            .read_to_end(&mut decompressed)?;
+        &decompressed
+    } else {
+        bytes
+    };
+
+    let mut reader = Reader { bytes };
+    if reader.array()? != [10] {
+        return Err(ModelError::Invalid("NBT root isn't a compound"));
+    }
+    reader.string()?;
+    reader.tag(10, 0)
+}
@@ -0,0 +1,459 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::game::vox::Vox;
+
+use super::{load_ron_or_default, lowercase_extension, ModelError, VoxModel};
+
+const SETTINGS_PATH: &str = "assets/models/points.ron";
+const DEFAULT_SETTINGS: &str = include_str!("../../../assets/models/points.ron");
+const MAX_BINS: usize = 1 << 24;
+// Points further out, in voxes, are more likely noise than scan
+const MAX_COORD: f64 = (1 << 24) as f64;
+
+#[derive(Deserialize)]
+pub struct PointSettings {
+    // The size of a vox, in the cloud's units
+    pub voxel_size: f64,
+    // Voxes with fewer points are dropped as noise
+    pub min_points: u32,
+}
+
+impl PointSettings {
+    pub fn load() -> Self {
+        load_ron_or_default(SETTINGS_PATH, DEFAULT_SETTINGS, |_| Ok(()))
+    }
+}
+
+// Sums the colors of the points in each vox, so only the voxes are kept in memory
+struct Bins {
+    voxel_size: f64,
+    bins: HashMap<IVec3, (Vec3, u32)>,
+}
+
+impl Bins {
+    fn new(voxel_size: f64) -> Result<Self, ModelError> {
+        match voxel_size > 0. {
+            true => Ok(Self {
+                voxel_size,
+                bins: HashMap::default(),
+            }),
+            false => Err(ModelError::Invalid("voxel size isn't positive")),
+        }
+    }
+
+    // Scans are usually Z-up, so they're rotated to be Y-up
+    fn add(&mut self, [x, y, z]: [f64; 3], color: Vec3) -> Result<(), ModelError> {
+        let pos = [x, z, -y].map(|coord| (coord / self.voxel_size).floor());
+        // Comparisons with NaN are false, so this rejects non-finite coordinates too
+        if !pos.iter().all(|coord| coord.abs() <= MAX_COORD) {
+            return Err(ModelError::Invalid("point is out of range"));
+        }
+        if !color.is_finite() {
+            return Err(ModelError::Invalid("point color isn't finite"));
+        }
+
+        let pos = pos.map(|coord| coord as i32);
+        if self.bins.len() >= MAX_BINS && !self.bins.contains_key(&IVec3::from(pos)) {
+            return Err(ModelError::Invalid("too many voxes for the voxel size"));
+        }
+
+        let (sum, count) = self.bins.entry(IVec3::from(pos)).or_default();
+        *sum += color;
+        *count += 1;
+        Ok(())
+    }
+
+    fn into_model(self, min_points: u32) -> VoxModel {
+        VoxModel::new(
+            self.bins
+                .into_iter()
+                .filter(|(_, (_, count))| *count >= min_points)
+                .map(|(pos, (sum, count))| {
+                    let color = sum / count as f32;
+                    (pos, Vox::solid(Color::rgb(color.x, color.y, color.z)))
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, ModelError> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(ModelError::Invalid("unknown PLY property type")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    // Integer colors span their type's range, and float colors span 0 to 1
+    fn color_range(self) -> f64 {
+        match self {
+            Self::I8 | Self::U8 => u8::MAX as f64,
+            Self::I16 | Self::U16 => u16::MAX as f64,
+            Self::I32 | Self::U32 => u32::MAX as f64,
+            Self::F32 | Self::F64 => 1.,
+        }
+    }
+
+    fn read(self, reader: &mut impl Read, big_endian: bool) -> Result<f64, ModelError> {
+        let mut bytes = [0; 8];
+        let len = self.size();
+        reader
+            .read_exact(&mut bytes[..len])
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::UnexpectedEof => ModelError::Truncated,
+                _ => err.into(),
+            })?;
+        if big_endian {
+            bytes[..len].reverse();
+        }
+
+        let [a, b, c, d, ..] = bytes;
+        Ok(match self {
+            Self::I8 => a as i8 as f64,
+            Self::U8 => a as f64,
+            Self::I16 => i16::from_le_bytes([a, b]) as f64,
+            Self::U16 => u16::from_le_bytes([a, b]) as f64,
+            Self::I32 => i32::from_le_bytes([a, b, c, d]) as f64,
+            Self::U32 => u32::from_le_bytes([a, b, c, d]) as f64,
+            Self::F32 => f32::from_le_bytes([a, b, c, d]) as f64,
+            Self::F64 => f64::from_le_bytes(bytes),
+        })
+    }
+}
+
+enum Property {
+    Scalar(Scalar),
+    List { count: Scalar, item: Scalar },
+}
+
+struct Element {
+    name: String,
+    count: u64,
+    properties: Vec<(String, Property)>,
+}
+
+#[derive(PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+fn line(reader: &mut impl BufRead, line: &mut String) -> Result<(), ModelError> {
+    line.clear();
+    match reader.read_line(line)? {
+        0 => Err(ModelError::Truncated),
+        _ => Ok(()),
+    }
+}
+
+fn read_header(reader: &mut impl BufRead) -> Result<(Format, Vec<Element>), ModelError> {
+    let mut text = String::new();
+    line(reader, &mut text)?;
+    if text.trim_end() != "ply" {
+        return Err(ModelError::Invalid("missing PLY magic"));
+    }
+
+    let mut format = None;
+    let mut elements = Vec::<Element>::new();
+    loop {
+        line(reader, &mut text)?;
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["format", format_name, ..] => {
+                format = Some(match *format_name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(ModelError::Invalid("unknown PLY format")),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| ModelError::Invalid("invalid PLY element count"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .ok_or(ModelError::Invalid("PLY property outside an element"))?
+                .properties
+                .push((
+                    name.to_string(),
+                    Property::List {
+                        count: match Scalar::parse(count)? {
+                            Scalar::F32 | Scalar::F64 => {
+                                return Err(ModelError::Invalid("PLY list count isn't an integer"))
+                            }
+                            count => count,
+                        },
+                        item: Scalar::parse(item)?,
+                    },
+                )),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .ok_or(ModelError::Invalid("PLY property outside an element"))?
+                .properties
+                .push((name.to_string(), Property::Scalar(Scalar::parse(scalar)?))),
+            ["end_header"] => break,
+            _ => (),
+        }
+    }
+
+    let format = format.ok_or(ModelError::Invalid("missing PLY format"))?;
+    Ok((format, elements))
+}
+
+// Reads one record's scalars into `values`, skipping lists
+fn read_record(
+    reader: &mut impl BufRead,
+    format: &Format,
+    element: &Element,
+    text: &mut String,
+    values: &mut Vec<f64>,
+) -> Result<(), ModelError> {
+    values.clear();
+    if *format == Format::Ascii {
+        line(reader, text)?;
+        let mut words = text.split_whitespace().map(|word| {
+            word.parse::<f64>()
+                .map_err(|_| ModelError::Invalid("invalid PLY value"))
+        });
+        let mut next = || words.next().unwrap_or(Err(ModelError::Truncated));
+        for (_, property) in &element.properties {
+            match property {
+                Property::Scalar(_) => values.push(next()?),
+                Property::List { .. } => {
+                    for _ in 0..next()? as u64 {
+                        next()?;
+                    }
+                    values.push(0.);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let big_endian = *format == Format::BigEndian;
+    for (_, property) in &element.properties {
+        match *property {
+            Property::Scalar(scalar) => values.push(scalar.read(reader, big_endian)?),
+            Property::List { count, item } => {
+                let len = (count.read(reader, big_endian)? as u64)
+                    .checked_mul(item.size() as u64)
+                    .ok_or(ModelError::Invalid("PLY list is too long"))?;
+                if io::copy(&mut reader.take(len), &mut io::sink())? < len {
+                    return Err(ModelError::Truncated);
+                }
+                values.push(0.);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn read_ply(mut reader: impl BufRead, bins: &mut Bins) -> Result<(), ModelError> {
+    let (format, elements) = read_header(&mut reader)?;
+    let mut text = String::new();
+    let mut values = Vec::new();
+    for element in &elements {
+        if element.name != "vertex" {
+            for _ in 0..element.count {
+                read_record(&mut reader, &format, element, &mut text, &mut values)?;
+            }
+            continue;
+        }
+
+        let find = |names: &[&str]| {
+            let mut properties = element.properties.iter().enumerate();
+            properties.find_map(|(i, (name, property))| match property {
+                Property::Scalar(scalar) if names.contains(&name.as_str()) => Some((i, *scalar)),
+                _ => None,
+            })
+        };
+        let position = match [find(&["x"]), find(&["y"]), find(&["z"])] {
+            [Some((x, _)), Some((y, _)), Some((z, _))] => [x, y, z],
+            _ => return Err(ModelError::Invalid("PLY vertices have no position")),
+        };
+        let color = [
+            find(&["red", "r", "diffuse_red"]),
+            find(&["green", "g", "diffuse_green"]),
+            find(&["blue", "b", "diffuse_blue"]),
+        ];
+        let color = match color {
+            [Some(r), Some(g), Some(b)] => {
+                Some([r, g, b].map(|(i, scalar)| (i, scalar.color_range())))
+            }
+            _ => None,
+        };
+
+        for _ in 0..element.count {
+            read_record(&mut reader, &format, element, &mut text, &mut values)?;
+            let color = color.map_or(Vec3::ONE, |color| {
+                Vec3::from(color.map(|(i, range)| (values[i] / range) as f32))
+            });
+            bins.add(position.map(|i| values[i]), color)?;
+        }
+        return Ok(());
+    }
+
+    Ok(())
+}
+
+// Lines of `x y z` with optional 8-bit `r g b`, separated by spaces or commas. Lines that aren't
+// points, like headers and point counts, are skipped.
+fn read_xyz(mut reader: impl BufRead, bins: &mut Bins) -> Result<(), ModelError> {
+    let mut text = String::new();
+    while reader.read_line(&mut text)? > 0 {
+        let values = text
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty())
+            .map(str::parse::<f64>)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_default();
+        if values.len() >= 3 {
+            let color = match values.get(3..6) {
+                Some([r, g, b]) => Vec3::new(*r as f32, *g as f32, *b as f32) / u8::MAX as f32,
+                _ => Vec3::ONE,
+            };
+            bins.add([values[0], values[1], values[2]], color)?;
+        }
+        text.clear();
+    }
+
+    Ok(())
+}
+
+pub fn read(path: &Path, settings: &PointSettings) -> Result<VoxModel, ModelError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut bins = Bins::new(settings.voxel_size)?;
+    match lowercase_extension(path).as_deref() {
+        Some("ply") => read_ply(reader, &mut bins)?,
+        Some("xyz" | "pts") => read_xyz(reader, &mut bins)?,
+        _ => return Err(ModelError::UnsupportedFormat(path.to_owned())),
+    }
+
+    Ok(bins.into_model(settings.min_points))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::model::{rgb, sorted};
+
+    fn header(format: &str) -> Vec<u8> {
+        format!(
+            "ply\nformat {} 1.0\ncomment test\nelement vertex 3\nproperty float x\n\
+            property float y\nproperty float z\nproperty uchar red\nproperty uchar green\n\
+            property uchar blue\nelement face 1\nproperty list uchar int vertex_indices\n\
+            end_header\n",
+            format
+        )
+        .into_bytes()
+    }
+
+    const POINTS: [([f32; 3], [u8; 3]); 3] = [
+        ([0.1, 0.1, 0.1], [100, 0, 0]),
+        ([0.2, 0.3, 0.4], [200, 0, 50]),
+        ([1.5, 0.1, 0.1], [0, 0, 255]),
+    ];
+
+    #[test]
+    fn averages_ascii_and_binary_ply() {
+        let mut ascii = header("ascii");
+        for ([x, y, z], [r, g, b]) in POINTS {
+            ascii.extend(format!("{} {} {} {} {} {}\n", x, y, z, r, g, b).into_bytes());
+        }
+        ascii.extend(b"3 0 1 2\n");
+
+        let mut binary = header("binary_big_endian");
+        for (pos, color) in POINTS {
+            binary.extend(pos.into_iter().flat_map(f32::to_be_bytes));
+            binary.extend(color);
+        }
+        binary.extend([3]);
+        binary.extend([0i32, 1, 2].into_iter().flat_map(i32::to_be_bytes));
+
+        for bytes in [ascii, binary] {
+            let mut bins = Bins::new(1.).unwrap();
+            read_ply(&bytes[..], &mut bins).unwrap();
+            assert_eq!(
+                sorted(&bins.into_model(1), |vox| rgb(vox.color)),
+                [([0, 0, 0], [150, 0, 25]), ([1, 0, 0], [0, 0, 255])]
+            );
+        }
+
+        let bytes = header("binary_little_endian");
+        assert!(read_ply(&bytes[..], &mut Bins::new(1.).unwrap()).is_err());
+    }
+
+    #[test]
+    fn rejects_float_list_counts() {
+        let header = String::from_utf8(header("binary_big_endian")).unwrap();
+        let header = header.replace("list uchar int", "list double int");
+        assert!(read_header(&mut header.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn drops_sparse_voxes() {
+        let xyz = "//X,Y,Z,R,G,B\n3\n0.1,0.1,0.1,255,0,0\n0.3 0.2 0.1 255 0 0\n5 5 5\n";
+        let mut bins = Bins::new(0.5).unwrap();
+        read_xyz(xyz.as_bytes(), &mut bins).unwrap();
+        assert_eq!(
+            sorted(&bins.into_model(2), |vox| rgb(vox.color)),
+            [([0, 0, 0], [255, 0, 0])]
+        );
+    }
+
+    #[test]
+    fn rejects_points_out_of_range() {
+        for xyz in [
+            "nan 0 0",
+            "0 inf 0",
+            "0 0 1e300",
+            "1e8 0 0",
+            "0 0 0 nan 0 0",
+        ] {
+            assert!(read_xyz(xyz.as_bytes(), &mut Bins::new(1.).unwrap()).is_err());
+        }
+        assert!(read_xyz("1e7 0 -1e7".as_bytes(), &mut Bins::new(1.).unwrap()).is_ok());
+    }
+}
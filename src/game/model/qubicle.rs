@@ -0,0 +1,293 @@
+use bevy::prelude::*;
+
+use crate::game::vox::Vox;
+
+use super::{rgb, ModelError, VoxModel};
+
+const VERSION: [u8; 4] = [1, 1, 0, 0];
+const RGBA: u32 = 0;
+const BGRA: u32 = 1;
+const RIGHT_HANDED: u32 = 1;
+const CODE_FLAG: u32 = 2;
+const NEXT_SLICE_FLAG: u32 = 6;
+const MAX_MATRIX_VOLUME: usize = 1 << 28;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ModelError> {
+        if len > self.bytes.len() {
+            return Err(ModelError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, ModelError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, ModelError> {
+        Ok(self.u32()? as i32)
+    }
+}
+
+struct Header {
+    bgra: bool,
+    right_handed: bool,
+    compressed: bool,
+}
+
+impl Header {
+    // Fully transparent voxes are empty. With the visibility mask encoded, the alpha of other
+    // voxes holds which faces are visible, so it's ignored either way.
+    fn vox(&self, color: u32) -> Option<Vox> {
+        let [r, g, b, a] = color.to_le_bytes();
+        let (r, b) = if self.bgra { (b, r) } else { (r, b) };
+        (a != 0).then(|| Vox::solid(Color::rgb_u8(r, g, b)))
+    }
+}
+
+fn read_matrix(
+    reader: &mut Reader,
+    header: &Header,
+    voxes: &mut Vec<(IVec3, Vox)>,
+) -> Result<(), ModelError> {
+    let name_len = reader.take(1)?[0] as usize;
+    reader.take(name_len)?;
+    let size = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+    let pos = IVec3::new(reader.i32()?, reader.i32()?, reader.i32()?);
+    let volume = (size.x as usize)
+        .checked_mul(size.y as usize)
+        .and_then(|area| area.checked_mul(size.z as usize));
+    if size.cmplt(IVec3::ZERO).any() || volume.is_none_or(|volume| volume > MAX_MATRIX_VOLUME) {
+        return Err(ModelError::Invalid("invalid matrix size"));
+    }
+
+    // With the far corner in range, mirrored positions are too
+    let far = [
+        pos.x.checked_add(size.x),
+        pos.y.checked_add(size.y),
+        pos.z.checked_add(size.z),
+    ];
+    if far.contains(&None) {
+        return Err(ModelError::Invalid("matrix position out of range"));
+    }
+
+    // Left-handed files mirror Z, so mirror it back around the origin
+    let mut push = |x: i32, y: i32, z: i32, color: u32| {
+        if let Some(vox) = header.vox(color) {
+            let z = match header.right_handed {
+                true => pos.z + z,
+                false => -1 - pos.z - z,
+            };
+            voxes.push((IVec3::new(pos.x + x, pos.y + y, z), vox));
+        }
+    };
+
+    let slice_area = size.x as usize * size.y as usize;
+    for z in 0..size.z {
+        if !header.compressed {
+            if slice_area * 4 > reader.bytes.len() {
+                return Err(ModelError::Truncated);
+            }
+
+            for i in 0..slice_area {
+                let (x, y) = ((i % size.x as usize) as i32, (i / size.x as usize) as i32);
+                push(x, y, z, reader.u32()?);
+            }
+            continue;
+        }
+
+        // Runs fill the slice row by row until the next slice flag
+        let mut i = 0;
+        loop {
+            let (count, color) = match reader.u32()? {
+                NEXT_SLICE_FLAG => break,
+                CODE_FLAG => (reader.u32()? as usize, reader.u32()?),
+                color => (1, color),
+            };
+            if i + count > slice_area {
+                return Err(ModelError::Invalid("run overflows its slice"));
+            }
+
+            for i in i..i + count {
+                let (x, y) = ((i % size.x as usize) as i32, (i / size.x as usize) as i32);
+                push(x, y, z, color);
+            }
+            i += count;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read(bytes: &[u8]) -> Result<VoxModel, ModelError> {
+    let mut reader = Reader { bytes };
+    if reader.take(4)? != VERSION {
+        return Err(ModelError::Invalid("unsupported Qubicle version"));
+    }
+
+    let header = Header {
+        bgra: match reader.u32()? {
+            RGBA => false,
+            BGRA => true,
+            _ => return Err(ModelError::Invalid("unknown color format")),
+        },
+        right_handed: reader.u32()? == RIGHT_HANDED,
+        compressed: reader.u32()? != 0,
+    };
+    reader.u32()?;
+
+    let mut voxes = Vec::new();
+    for _ in 0..reader.u32()? {
+        read_matrix(&mut reader, &header, &mut voxes)?;
+    }
+
+    VoxModel::try_new(voxes)
+}
+
+fn rgba(vox: &Vox) -> u32 {
+    let [r, g, b] = rgb(vox.color);
+    u32::from_le_bytes([r, g, b, 0xff])
+}
+
+// Writes a single right-handed, RGBA, run-length encoded matrix
+pub fn write(model: &VoxModel, name: &str) -> Vec<u8> {
+    let size = model.size.max(IVec3::ZERO);
+    let slice_area = size.x as usize * size.y as usize;
+    let mut colors = vec![0; slice_area * size.z as usize];
+    for (pos, vox) in &model.voxes {
+        let i = pos.x as usize + pos.y as usize * size.x as usize + pos.z as usize * slice_area;
+        colors[i] = rgba(vox);
+    }
+
+    let name = &name.as_bytes()[..name.len().min(u8::MAX as usize)];
+    let mut bytes = VERSION.to_vec();
+    for value in [RGBA, RIGHT_HANDED, 1, 0, 1] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.push(name.len() as u8);
+    bytes.extend(name);
+    for value in size.to_array().into_iter().chain([0; 3]) {
+        bytes.extend(value.to_le_bytes());
+    }
+
+    for slice in colors.chunks(slice_area.max(1)) {
+        let mut i = 0;
+        while i < slice.len() {
+            let color = slice[i];
+            let count = slice[i..].iter().take_while(|run| **run == color).count();
+            // Short runs of colors that don't clash with the flags are cheaper stored plainly
+            if count > 2 || color == CODE_FLAG || color == NEXT_SLICE_FLAG {
+                bytes.extend(CODE_FLAG.to_le_bytes());
+                bytes.extend((count as u32).to_le_bytes());
+                bytes.extend(color.to_le_bytes());
+            } else {
+                for _ in 0..count {
+                    bytes.extend(color.to_le_bytes());
+                }
+            }
+            i += count;
+        }
+        bytes.extend(NEXT_SLICE_FLAG.to_le_bytes());
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::model::{assert_rejects_truncated, sorted};
+
+    #[test]
+    fn round_trips_models() {
+        let model = VoxModel::new(
+            (0..40)
+                .map(|i| {
+                    let color = Color::rgb_u8((i / 7 * 30) as u8, 100, 0);
+                    (IVec3::new(i % 5, i / 5 % 3, i / 15), Vox::solid(color))
+                })
+                .collect(),
+        );
+
+        let read = read(&write(&model, "region")).unwrap();
+        assert_eq!(read.size, model.size);
+        assert_eq!(sorted(&read, rgba), sorted(&model, rgba));
+    }
+
+    // Uncompressed, left-handed, BGRA matrices of a single vox each
+    fn single_voxes(matrices: &[([i32; 3], [i32; 3], [u8; 4])]) -> Vec<u8> {
+        let mut bytes = VERSION.to_vec();
+        for value in [BGRA, 0, 0, 0, matrices.len() as u32] {
+            bytes.extend(value.to_le_bytes());
+        }
+
+        for (size, pos, color) in matrices {
+            bytes.extend([1, b'm']);
+            for value in size.iter().chain(pos) {
+                bytes.extend(value.to_le_bytes());
+            }
+            bytes.extend(color);
+        }
+
+        bytes
+    }
+
+    // One vox at (0, 0, 0) and another at (2, 0, -1) relative to it
+    fn matrices() -> Vec<u8> {
+        single_voxes(&[
+            ([1, 1, 1], [0, 0, 0], [0x30, 0x20, 0x10, 0xff]),
+            ([1, 1, 1], [2, 0, -1], [1, 2, 3, 4]),
+        ])
+    }
+
+    #[test]
+    fn reads_bgra_left_handed_matrices() {
+        let model = read(&matrices()).unwrap();
+        assert_eq!(
+            sorted(&model, rgba),
+            [
+                ([0, 0, 0], u32::from_le_bytes([0x10, 0x20, 0x30, 0xff])),
+                ([2, 0, 1], u32::from_le_bytes([3, 2, 1, 0xff])),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_matrices_out_of_range() {
+        let color = [1, 2, 3, 0xff];
+        for (size, pos) in [
+            ([i32::MAX; 3], [0; 3]),
+            ([1, 1, 1], [i32::MAX, 0, 0]),
+            ([1, 1, 1], [0, 0, i32::MAX]),
+            ([1, 1, 1], [0, i32::MAX, 0]),
+        ] {
+            assert!(read(&single_voxes(&[(size, pos, color)])).is_err());
+        }
+        assert!(read(&single_voxes(&[([1, 1, 1], [0, 0, i32::MIN], color)])).is_ok());
+
+        let far_apart = [
+            ([1, 1, 1], [i32::MIN, 0, 0], color),
+            ([1, 1, 1], [i32::MAX - 1, 0, 0], color),
+        ];
+        assert!(read(&single_voxes(&far_apart)).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        assert_rejects_truncated(&matrices(), read);
+        assert_rejects_truncated(
+            &write(
+                &VoxModel::new(vec![(IVec3::ZERO, Vox::solid(Color::RED))]),
+                "",
+            ),
+            read,
+        );
+    }
+}
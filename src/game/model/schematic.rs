@@ -0,0 +1,394 @@
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::Deserialize;
+
+use crate::game::vox::{Material, Vox};
+
+use super::{
+    load_ron_or_default,
+    nbt::{self, Tag},
+    ModelError, VoxModel,
+};
+
+const BLOCKS_PATH: &str = "assets/models/blocks.ron";
+const DEFAULT_BLOCKS: &str = include_str!("../../../assets/models/blocks.ron");
+const DEFAULT_NAMESPACE: &str = "minecraft:";
+// Variants like stairs and slabs fall back to the block they're made of
+const VARIANT_SUFFIXES: &[&str] = &[
+    "_stairs",
+    "_slab",
+    "_wall",
+    "_fence_gate",
+    "_fence",
+    "_pressure_plate",
+    "_button",
+    "_trapdoor",
+    "_door",
+];
+const MAX_VOLUME: usize = 1 << 28;
+
+#[derive(Deserialize)]
+pub struct BlockConfig {
+    pub color: Color,
+    #[serde(default)]
+    pub material: u16,
+}
+
+#[derive(Deserialize)]
+pub struct BlockTable {
+    pub empty: HashSet<String>,
+    pub unknown: Color,
+    pub blocks: HashMap<String, BlockConfig>,
+    // Legacy numeric IDs, as `id:data` or just `id`, to block names
+    pub legacy: HashMap<String, String>,
+}
+
+impl BlockTable {
+    pub fn load() -> Self {
+        load_ron_or_default(BLOCKS_PATH, DEFAULT_BLOCKS, Self::validate)
+    }
+
+    // Blocks can only use built-in materials, since the rest belong to ores
+    fn validate(&self) -> Result<(), String> {
+        match self
+            .blocks
+            .iter()
+            .find(|(_, config)| !Material::BUILT_IN.contains(&Material(config.material)))
+        {
+            Some((name, config)) => Err(format!(
+                "{}: material {} isn't a built-in material",
+                name, config.material
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn config(&self, name: &str) -> Option<&BlockConfig> {
+        self.blocks.get(name).or_else(|| {
+            let base = VARIANT_SUFFIXES
+                .iter()
+                .find_map(|suffix| name.strip_suffix(suffix))?;
+            [
+                base.to_owned(),
+                format!("{}s", base),
+                format!("{}_planks", base),
+            ]
+            .iter()
+            .find_map(|name| self.blocks.get(name))
+        })
+    }
+
+    // Block states like `minecraft:oak_stairs[facing=east]` are looked up by their block name
+    fn vox(&self, state: &str, unknown: &mut HashSet<String>) -> Option<Vox> {
+        let name = state.split('[').next().unwrap_or_default();
+        let name = match name.contains(':') {
+            true => name.to_owned(),
+            false => format!("{}{}", DEFAULT_NAMESPACE, name),
+        };
+
+        if self.empty.contains(&name) {
+            return None;
+        }
+
+        Some(match self.config(&name) {
+            Some(config) => Vox::new(config.color, Material(config.material)),
+            None => {
+                unknown.insert(name);
+                Vox::solid(self.unknown)
+            }
+        })
+    }
+
+    fn legacy_vox(&self, id: u16, data: u8, unknown: &mut HashSet<String>) -> Option<Vox> {
+        match self
+            .legacy
+            .get(&format!("{}:{}", id, data))
+            .or_else(|| self.legacy.get(&id.to_string()))
+        {
+            Some(name) => self.vox(name, unknown),
+            None => {
+                unknown.insert(format!("{}:{}", id, data));
+                Some(Vox::solid(self.unknown))
+            }
+        }
+    }
+}
+
+fn size(schematic: &Tag) -> Result<IVec3, ModelError> {
+    let dimension = |name| {
+        schematic
+            .get(name)
+            .and_then(Tag::int)
+            .map(|dimension| dimension & 0xffff)
+            .ok_or(ModelError::Invalid("schematic is missing its dimensions"))
+    };
+    let size = IVec3::new(
+        dimension("Width")?,
+        dimension("Height")?,
+        dimension("Length")?,
+    );
+
+    if size.x as usize * size.y as usize * size.z as usize > MAX_VOLUME {
+        return Err(ModelError::Invalid("schematic is too large"));
+    }
+    Ok(size)
+}
+
+// Blocks are ordered by X, then Z, then Y
+fn block_pos(i: usize, size: IVec3) -> IVec3 {
+    let (width, length) = (size.x as usize, size.z as usize);
+    IVec3::new(
+        (i % width) as i32,
+        (i / (width * length)) as i32,
+        (i / width % length) as i32,
+    )
+}
+
+fn read_varint(bytes: &mut impl Iterator<Item = u8>) -> Result<i32, ModelError> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = bytes.next().ok_or(ModelError::Truncated)?;
+        value |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+
+    Err(ModelError::Invalid("varint is too long"))
+}
+
+fn read_sponge(
+    palette: &Tag,
+    data: &Tag,
+    size: IVec3,
+    table: &BlockTable,
+    unknown: &mut HashSet<String>,
+) -> Result<Vec<(IVec3, Vox)>, ModelError> {
+    let palette = palette
+        .compound()
+        .ok_or(ModelError::Invalid("schematic is missing its palette"))?
+        .iter()
+        .map(|(state, index)| {
+            let index = index
+                .int()
+                .ok_or(ModelError::Invalid("invalid palette index"))?;
+            Ok((index, table.vox(state, unknown)))
+        })
+        .collect::<Result<HashMap<_, _>, ModelError>>()?;
+    let mut data = data
+        .bytes()
+        .ok_or(ModelError::Invalid("schematic is missing its block data"))?
+        .iter()
+        .copied();
+
+    let mut voxes = Vec::new();
+    for i in 0..(size.x * size.y * size.z) as usize {
+        let vox = palette
+            .get(&read_varint(&mut data)?)
+            .ok_or(ModelError::Invalid("block data isn't in the palette"))?;
+        if let Some(vox) = vox {
+            voxes.push((block_pos(i, size), vox.clone()));
+        }
+    }
+
+    Ok(voxes)
+}
+
+// MCEdit schematics store 8-bit IDs with optional extra nibbles, and 4 bits of data per block
+fn read_legacy(
+    schematic: &Tag,
+    blocks: &[u8],
+    size: IVec3,
+    table: &BlockTable,
+    unknown: &mut HashSet<String>,
+) -> Result<Vec<(IVec3, Vox)>, ModelError> {
+    let volume = (size.x * size.y * size.z) as usize;
+    let data = schematic
+        .get("Data")
+        .and_then(Tag::bytes)
+        .unwrap_or_default();
+    let add = schematic
+        .get("AddBlocks")
+        .and_then(Tag::bytes)
+        .unwrap_or_default();
+    if blocks.len() != volume || !(data.is_empty() || data.len() == volume) {
+        return Err(ModelError::Invalid(
+            "block data doesn't match the dimensions",
+        ));
+    }
+
+    let mut cache = HashMap::default();
+    let mut voxes = Vec::new();
+    for (i, block) in blocks.iter().enumerate() {
+        let add = add.get(i >> 1).map_or(0, |add| match i & 1 {
+            0 => (add & 0x0f) as u16,
+            _ => (add >> 4) as u16,
+        });
+        let id = add << 8 | *block as u16;
+        let data = data.get(i).map_or(0, |data| data & 0x0f);
+
+        let vox = cache
+            .entry((id, data))
+            .or_insert_with(|| table.legacy_vox(id, data, unknown));
+        if let Some(vox) = vox {
+            voxes.push((block_pos(i, size), vox.clone()));
+        }
+    }
+
+    Ok(voxes)
+}
+
+pub fn read(bytes: &[u8], table: &BlockTable) -> Result<VoxModel, ModelError> {
+    let root = nbt::read(bytes)?;
+    // Sponge v3 nests everything in a `Schematic` compound
+    let schematic = root
+        .get("Schematic")
+        .filter(|schematic| schematic.compound().is_some())
+        .unwrap_or(&root);
+    let size = size(schematic)?;
+
+    let mut unknown = HashSet::default();
+    let voxes = match (
+        schematic.get("Blocks"),
+        schematic.get("Palette"),
+        schematic.get("BlockData"),
+    ) {
+        (Some(Tag::Compound(blocks)), _, _) => match (blocks.get("Palette"), blocks.get("Data")) {
+            (Some(palette), Some(data)) => read_sponge(palette, data, size, table, &mut unknown)?,
+            _ => return Err(ModelError::Invalid("schematic is missing its block data")),
+        },
+        (Some(Tag::ByteArray(blocks)), _, _) => {
+            read_legacy(schematic, blocks, size, table, &mut unknown)?
+        }
+        (_, Some(palette), Some(data)) => read_sponge(palette, data, size, table, &mut unknown)?,
+        _ => return Err(ModelError::Invalid("schematic is missing its block data")),
+    };
+
+    if !unknown.is_empty() {
+        let mut unknown = unknown.into_iter().collect::<Vec<_>>();
+        unknown.sort_unstable();
+        warn!("Unknown blocks in schematic: {}", unknown.join(", "));
+    }
+
+    Ok(VoxModel::new(voxes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::model::{assert_rejects_truncated, sorted};
+
+    fn named(id: u8, name: &str, payload: &[u8]) -> Vec<u8> {
+        [
+            &[id][..],
+            &(name.len() as u16).to_be_bytes(),
+            name.as_bytes(),
+            payload,
+        ]
+        .concat()
+    }
+
+    fn short(name: &str, value: i16) -> Vec<u8> {
+        named(2, name, &value.to_be_bytes())
+    }
+
+    fn byte_array(name: &str, bytes: &[u8]) -> Vec<u8> {
+        named(
+            7,
+            name,
+            &[&(bytes.len() as i32).to_be_bytes()[..], bytes].concat(),
+        )
+    }
+
+    fn compound(name: &str, tags: &[Vec<u8>]) -> Vec<u8> {
+        named(10, name, &[tags.concat(), vec![0]].concat())
+    }
+
+    fn dimensions() -> Vec<Vec<u8>> {
+        vec![short("Width", 2), short("Height", 2), short("Length", 1)]
+    }
+
+    fn palette() -> Vec<u8> {
+        compound(
+            "Palette",
+            &[
+                named(3, "minecraft:air", &0i32.to_be_bytes()),
+                named(3, "minecraft:stone", &1i32.to_be_bytes()),
+                named(3, "water[level=0]", &200i32.to_be_bytes()),
+            ],
+        )
+    }
+
+    fn assert_blocks(bytes: &[u8]) {
+        let table = ron::from_str::<BlockTable>(DEFAULT_BLOCKS).unwrap();
+        let model = read(bytes, &table).unwrap();
+        assert_eq!(model.size, IVec3::new(2, 2, 1));
+        assert_eq!(
+            sorted(&model, |vox| vox.material.0),
+            [([0, 0, 0], 0), ([1, 1, 0], 1)]
+        );
+        assert_eq!(
+            model.voxes[0].1.color,
+            table.blocks["minecraft:stone"].color
+        );
+    }
+
+    #[test]
+    fn reads_sponge_v2() {
+        let mut tags = dimensions();
+        tags.push(palette());
+        // Index 200 takes two bytes as a varint
+        tags.push(byte_array("BlockData", &[1, 0, 0, 200, 1]));
+        assert_blocks(&compound("Schematic", &tags));
+    }
+
+    #[test]
+    fn reads_sponge_v3() {
+        let mut tags = dimensions();
+        tags.push(compound(
+            "Blocks",
+            &[palette(), byte_array("Data", &[1, 0, 0, 200, 1])],
+        ));
+        assert_blocks(&compound("", &[compound("Schematic", &tags)]));
+    }
+
+    #[test]
+    fn reads_legacy_schematics() {
+        let mut tags = dimensions();
+        tags.push(byte_array("Blocks", &[1, 0, 0, 9]));
+        tags.push(byte_array("Data", &[0, 0, 0, 0]));
+        assert_blocks(&compound("Schematic", &tags));
+    }
+
+    #[test]
+    fn falls_back_to_variant_bases() {
+        let table = ron::from_str::<BlockTable>(DEFAULT_BLOCKS).unwrap();
+        let mut unknown = HashSet::default();
+        let vox = table
+            .vox("minecraft:spruce_stairs[facing=east]", &mut unknown)
+            .unwrap();
+        assert_eq!(vox.color, table.blocks["minecraft:spruce_planks"].color);
+        assert!(table.vox("minecraft:cave_air", &mut unknown).is_none());
+        assert!(unknown.is_empty());
+    }
+
+    #[test]
+    fn validates_block_materials() {
+        let mut table = ron::from_str::<BlockTable>(DEFAULT_BLOCKS).unwrap();
+        assert!(table.validate().is_ok());
+        table.blocks.get_mut("minecraft:stone").unwrap().material = 7;
+        assert!(table.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_schematics() {
+        let table = ron::from_str::<BlockTable>(DEFAULT_BLOCKS).unwrap();
+        let mut tags = dimensions();
+        tags.push(palette());
+        tags.push(byte_array("BlockData", &[1, 0, 0, 200, 1]));
+        let bytes = compound("Schematic", &tags);
+        assert_rejects_truncated(&bytes, |bytes| read(bytes, &table));
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use image::{Rgba, RgbaImage};
+
+use crate::game::vox::Vox;
+
+use super::{lowercase_extension, rgb, ModelError, VoxModel};
+
+// Pixels at least half opaque are solid
+const MIN_ALPHA: u8 = 128;
+
+// Slices are ordered by the last number in their name, so unpadded numbering works too
+fn slice_key(path: &Path) -> (Option<u64>, String) {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let number = stem
+        .rsplit(|c: char| !c.is_ascii_digit())
+        .find(|digits| !digits.is_empty())
+        .and_then(|digits| digits.parse().ok());
+    (number, stem.into_owned())
+}
+
+// Each image is a Y layer from the bottom up, with rows going along Z
+fn push_slice(y: i32, image: &RgbaImage, voxes: &mut Vec<(IVec3, Vox)>) {
+    for (x, z, Rgba([r, g, b, a])) in image.enumerate_pixels() {
+        if *a >= MIN_ALPHA {
+            let pos = IVec3::new(x as i32, y, z as i32);
+            voxes.push((pos, Vox::solid(Color::rgb_u8(*r, *g, *b))));
+        }
+    }
+}
+
+fn slice(model: &VoxModel, y: i32) -> RgbaImage {
+    let mut image = RgbaImage::new(model.size.x as u32, model.size.z as u32);
+    for (pos, vox) in model.voxes.iter().filter(|(pos, _)| pos.y == y) {
+        let [r, g, b] = rgb(vox.color);
+        image.put_pixel(pos.x as u32, pos.z as u32, Rgba([r, g, b, u8::MAX]));
+    }
+    image
+}
+
+fn png_paths(dir: &Path) -> Result<Vec<PathBuf>, ModelError> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.retain(|path| lowercase_extension(path).as_deref() == Some("png"));
+    Ok(paths)
+}
+
+pub fn read(dir: &Path) -> Result<VoxModel, ModelError> {
+    let mut paths = png_paths(dir)?;
+    paths.sort_by_cached_key(|path| slice_key(path));
+    if paths.is_empty() {
+        return Err(ModelError::Invalid("no PNG slices in the directory"));
+    }
+
+    let mut voxes = Vec::new();
+    for (y, path) in paths.iter().enumerate() {
+        push_slice(y as i32, &image::open(path)?.to_rgba8(), &mut voxes);
+    }
+
+    Ok(VoxModel::new(voxes))
+}
+
+// Writes a PNG per layer, named by its height so they sort in order. Slices already in the
+// directory are removed first, so a shorter model doesn't pick up an old model's top layers.
+pub fn write(model: &VoxModel, dir: &Path) -> Result<(), ModelError> {
+    fs::create_dir_all(dir)?;
+    for path in png_paths(dir)? {
+        fs::remove_file(path)?;
+    }
+    if model.voxes.is_empty() {
+        return Ok(());
+    }
+
+    for y in 0..model.size.y {
+        slice(model, y).save(dir.join(format!("{:04}.png", y)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::model::sorted;
+
+    #[test]
+    fn round_trips_slices() {
+        let model = VoxModel::new(
+            (0..24)
+                .filter(|i| i % 5 != 0)
+                .map(|i| {
+                    let color = Color::rgb_u8((i * 10) as u8, 0, 255);
+                    (IVec3::new(i % 2, i / 2 % 3, i / 6), Vox::solid(color))
+                })
+                .collect(),
+        );
+
+        let mut voxes = Vec::new();
+        for y in 0..model.size.y {
+            push_slice(y, &slice(&model, y), &mut voxes);
+        }
+
+        let colors = |vox: &Vox| format!("{:?}", vox.color);
+        assert_eq!(
+            sorted(&VoxModel::new(voxes), colors),
+            sorted(&model, colors)
+        );
+    }
+
+    #[test]
+    fn round_trips_slice_directories() {
+        let dir = std::env::temp_dir().join(format!("voxmod-slices-{}", std::process::id()));
+        let column = |height: i32| {
+            VoxModel::new(
+                (0..height)
+                    .map(|y| (IVec3::new(y % 2, y, 0), Vox::solid(Color::RED)))
+                    .collect(),
+            )
+        };
+
+        for height in [12, 3] {
+            let model = column(height);
+            write(&model, &dir).unwrap();
+            let read = read(&dir).unwrap();
+            assert_eq!(read.size, model.size);
+            assert_eq!(
+                sorted(&read, |vox| rgb(vox.color)),
+                sorted(&model, |vox| rgb(vox.color))
+            );
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn orders_slices_by_number() {
+        let mut paths = ["scan10.png", "scan2.png", "scan1.png", "top.png"].map(Path::new);
+        paths.sort_by_cached_key(|path| slice_key(path));
+        assert_eq!(
+            paths,
+            ["top.png", "scan1.png", "scan2.png", "scan10.png"].map(Path::new)
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use std::{fs, path::Path};
+
+use bevy::{prelude::*, utils::HashMap};
+use gltf::{buffer, image as gltf_image, mesh::Mode, Gltf};
+
+use super::{lowercase_extension, ModelError};
+
+const MAX_NODE_DEPTH: usize = 64;
+const MAX_VISITS: usize = 1 << 16;
+const MAX_TRIANGLES: usize = 1 << 24;
+
+// Colors are sRGB, and textures are sampled with V pointing down, as in glTF
+pub struct Triangle {
+    pub positions: [Vec3; 3],
+    pub colors: [Vec4; 3],
+    pub uvs: [Vec2; 3],
+    pub texture: Option<usize>,
+}
+
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl Texture {
+    fn decode(bytes: &[u8]) -> Result<Self, image::ImageError> {
+        let image = image::load_from_memory(bytes)?.to_rgba8();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|pixel| pixel.0).collect(),
+        })
+    }
+
+    // Nearest neighbor, repeating outside the texture
+    pub fn sample(&self, uv: Vec2) -> Vec4 {
+        let x = ((uv.x.rem_euclid(1.) * self.width as f32) as u32).min(self.width - 1);
+        let y = ((uv.y.rem_euclid(1.) * self.height as f32) as u32).min(self.height - 1);
+        Vec4::from(self.pixels[(x + y * self.width) as usize].map(|channel| channel as f32 / 255.))
+    }
+}
+
+#[derive(Default)]
+pub struct TriangleMesh {
+    pub triangles: Vec<Triangle>,
+    pub textures: Vec<Texture>,
+}
+
+impl TriangleMesh {
+    pub fn load(path: &Path) -> Result<Self, ModelError> {
+        match lowercase_extension(path).as_deref() {
+            Some("obj") => read_obj(
+                &fs::read_to_string(path)?,
+                path.parent().unwrap_or_else(|| Path::new("")),
+            ),
+            Some("stl") => read_stl(&fs::read(path)?),
+            Some("gltf" | "glb") => read_gltf(path),
+            _ => Err(ModelError::UnsupportedFormat(path.to_owned())),
+        }
+    }
+
+    // Textures that fail to load are skipped, so the mesh keeps its other colors
+    fn add_texture(&mut self, name: &str, bytes: Result<Vec<u8>, ModelError>) -> Option<usize> {
+        match bytes.and_then(|bytes| Texture::decode(&bytes).map_err(ModelError::from)) {
+            Ok(texture) => {
+                self.textures.push(texture);
+                Some(self.textures.len() - 1)
+            }
+            Err(err) => {
+                warn!("Failed to load texture {}: {}", name, err);
+                None
+            }
+        }
+    }
+}
+
+fn floats<const N: usize>(words: &[&str]) -> Result<[f32; N], ModelError> {
+    let mut floats = [0.; N];
+    for (float, word) in floats.iter_mut().zip(words) {
+        *float = word
+            .parse()
+            .map_err(|_| ModelError::Invalid("invalid number"))?;
+    }
+
+    match words.len() >= N {
+        true => Ok(floats),
+        false => Err(ModelError::Truncated),
+    }
+}
+
+struct ObjMaterial {
+    color: Vec4,
+    texture: Option<usize>,
+}
+
+fn read_mtl(
+    path: &Path,
+    mesh: &mut TriangleMesh,
+    materials: &mut HashMap<String, ObjMaterial>,
+) -> Result<(), ModelError> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut name = None;
+    for line in fs::read_to_string(path)?.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            ["newmtl", new_name, ..] => {
+                name = Some(new_name.to_string());
+                materials.insert(
+                    new_name.to_string(),
+                    ObjMaterial {
+                        color: Vec4::ONE,
+                        texture: None,
+                    },
+                );
+            }
+            ["Kd", color @ ..] => {
+                if let Some(material) = name.as_ref().and_then(|name| materials.get_mut(name)) {
+                    material.color = Vec3::from(floats::<3>(color)?).extend(1.);
+                }
+            }
+            // Options come before the file name, so it's always last
+            ["map_Kd", .., file] => {
+                if let Some(material) = name.as_ref().and_then(|name| materials.get_mut(name)) {
+                    let bytes = fs::read(dir.join(file)).map_err(ModelError::from);
+                    material.texture = mesh.add_texture(file, bytes);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+// Negative indices count back from the latest element
+fn obj_index(word: &str, len: usize) -> Result<usize, ModelError> {
+    let index = word
+        .parse::<i64>()
+        .map_err(|_| ModelError::Invalid("invalid index"))?;
+    let index = match index {
+        index if index < 0 => len as i64 + index,
+        index => index - 1,
+    };
+
+    usize::try_from(index)
+        .ok()
+        .filter(|index| *index < len)
+        .ok_or(ModelError::Invalid("index is out of range"))
+}
+
+// Material libraries and textures are relative to `dir`
+pub fn read_obj(text: &str, dir: &Path) -> Result<TriangleMesh, ModelError> {
+    let mut mesh = TriangleMesh::default();
+    let mut materials = HashMap::default();
+    let mut material = None;
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut uvs = Vec::new();
+
+    for line in text.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            // Colors after positions are a common extension
+            ["v", values @ ..] => {
+                positions.push(Vec3::from(floats::<3>(values)?));
+                colors.push(match values.len() {
+                    6.. => Vec3::from(floats::<3>(&values[3..])?).extend(1.),
+                    _ => Vec4::ONE,
+                });
+            }
+            ["vt", values @ ..] => {
+                let [u, v] = floats(values)?;
+                uvs.push(Vec2::new(u, 1. - v));
+            }
+            ["mtllib", file] => {
+                if let Err(err) = read_mtl(&dir.join(file), &mut mesh, &mut materials) {
+                    warn!("Failed to load materials {}: {}", file, err);
+                }
+            }
+            ["usemtl", name] => {
+                material = materials
+                    .get(*name)
+                    .map(|material: &ObjMaterial| (material.color, material.texture))
+            }
+            ["f", corners @ ..] => {
+                let corners = corners
+                    .iter()
+                    .map(|corner| {
+                        let mut indices = corner.split('/');
+                        let position = obj_index(indices.next().unwrap(), positions.len())?;
+                        let uv = match indices.next() {
+                            Some(uv) if !uv.is_empty() => uvs[obj_index(uv, uvs.len())?],
+                            _ => Vec2::ZERO,
+                        };
+                        Ok((position, uv))
+                    })
+                    .collect::<Result<Vec<_>, ModelError>>()?;
+
+                let (color, texture) = material.unwrap_or((Vec4::ONE, None));
+                for i in 1..corners.len().saturating_sub(1) {
+                    let corners = [corners[0], corners[i], corners[i + 1]];
+                    mesh.triangles.push(Triangle {
+                        positions: corners.map(|(position, _)| positions[position]),
+                        colors: corners.map(|(position, _)| colors[position] * color),
+                        uvs: corners.map(|(_, uv)| uv),
+                        texture,
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(mesh)
+}
+
+// STL is usually Z-up, so it's rotated to be Y-up
+fn read_stl(bytes: &[u8]) -> Result<TriangleMesh, ModelError> {
+    let y_up = |[x, y, z]: [f32; 3]| Vec3::new(x, z, -y);
+    let mut mesh = TriangleMesh::default();
+    let mut push = |positions: [Vec3; 3]| {
+        mesh.triangles.push(Triangle {
+            positions,
+            colors: [Vec4::ONE; 3],
+            uvs: [Vec2::ZERO; 3],
+            texture: None,
+        })
+    };
+
+    // ASCII files can start with `solid` too, so binary files are told apart by their length
+    let count = bytes
+        .get(80..84)
+        .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    let binary = count.is_some_and(|count| {
+        count
+            .checked_mul(50)
+            .is_some_and(|len| len + 84 == bytes.len())
+    });
+    if binary {
+        for triangle in bytes[84..].chunks_exact(50) {
+            let vertex = |i: usize| {
+                let offset = 12 + i * 12;
+                y_up([0, 4, 8].map(|float| {
+                    f32::from_le_bytes(
+                        triangle[offset + float..offset + float + 4]
+                            .try_into()
+                            .unwrap(),
+                    )
+                }))
+            };
+            push([vertex(0), vertex(1), vertex(2)]);
+        }
+    } else {
+        let text = std::str::from_utf8(bytes).map_err(|_| ModelError::Invalid("invalid STL"))?;
+        let mut vertices = Vec::new();
+        for line in text.lines() {
+            if let ["vertex", values @ ..] = line.split_whitespace().collect::<Vec<_>>().as_slice()
+            {
+                vertices.push(y_up(floats(values)?));
+            }
+        }
+
+        if vertices.len() % 3 != 0 {
+            return Err(ModelError::Truncated);
+        }
+        for triangle in vertices.chunks_exact(3) {
+            push([triangle[0], triangle[1], triangle[2]]);
+        }
+    }
+
+    Ok(mesh)
+}
+
+// Data URIs are decoded, and other URIs are files relative to the glTF file
+fn read_uri(dir: &Path, uri: &str) -> Result<Vec<u8>, ModelError> {
+    match uri.strip_prefix("data:") {
+        Some(data) => {
+            let (_, data) = data
+                .split_once(";base64,")
+                .ok_or(ModelError::Invalid("unsupported data URI"))?;
+            base64::decode(data).map_err(|_| ModelError::Invalid("invalid base64"))
+        }
+        None => Ok(fs::read(dir.join(uri))?),
+    }
+}
+
+fn srgb(linear: [f32; 4]) -> Vec4 {
+    Vec4::from(Color::rgba_linear(linear[0], linear[1], linear[2], linear[3]).as_rgba_f32())
+}
+
+fn read_gltf(path: &Path) -> Result<TriangleMesh, ModelError> {
+    gltf_mesh(
+        &Gltf::from_slice(&fs::read(path)?)?,
+        path.parent().unwrap_or_else(|| Path::new("")),
+    )
+}
+
+// Nodes can list themselves or their ancestors as children, and several nodes can share
+// children, so the hierarchy's depth, the nodes and primitives it reaches and the triangles they
+// add up to are capped
+fn gltf_mesh(gltf: &Gltf, dir: &Path) -> Result<TriangleMesh, ModelError> {
+    let buffers = gltf
+        .buffers()
+        .map(|buffer| match buffer.source() {
+            buffer::Source::Bin => gltf
+                .blob
+                .clone()
+                .ok_or(ModelError::Invalid("missing binary chunk")),
+            buffer::Source::Uri(uri) => read_uri(dir, uri),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut mesh = TriangleMesh::default();
+    let mut textures = HashMap::default();
+    let mut nodes = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .into_iter()
+        .flat_map(|scene| scene.nodes())
+        .map(|node| (node, Mat4::IDENTITY, 0))
+        .collect::<Vec<_>>();
+
+    let mut visits = 0;
+    while let Some((node, parent, depth)) = nodes.pop() {
+        if depth > MAX_NODE_DEPTH {
+            return Err(ModelError::Invalid("node hierarchy is too deep or cyclic"));
+        }
+        visits += 1;
+        if visits > MAX_VISITS {
+            return Err(ModelError::Invalid("node hierarchy is too large"));
+        }
+
+        let transform = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+        nodes.extend(node.children().map(|child| (child, transform, depth + 1)));
+
+        let primitives = node.mesh().into_iter().flat_map(|mesh| mesh.primitives());
+        for primitive in primitives.filter(|primitive| primitive.mode() == Mode::Triangles) {
+            visits += 1;
+            if visits > MAX_VISITS {
+                return Err(ModelError::Invalid("node hierarchy is too large"));
+            }
+
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let positions = match reader.read_positions() {
+                Some(positions) => positions
+                    .map(|pos| transform.transform_point3(Vec3::from(pos)))
+                    .collect::<Vec<_>>(),
+                None => continue,
+            };
+            let colors = reader.read_colors(0).map_or_else(
+                || vec![Vec4::ONE; positions.len()],
+                |colors| colors.into_rgba_f32().map(srgb).collect(),
+            );
+
+            let pbr = primitive.material().pbr_metallic_roughness();
+            let color = srgb(pbr.base_color_factor());
+            let (texture, uvs) = match pbr.base_color_texture() {
+                Some(info) => {
+                    let image = info.texture().source();
+                    let texture = *textures.entry(image.index()).or_insert_with(|| {
+                        let bytes = match image.source() {
+                            gltf_image::Source::View { view, .. } => buffers
+                                .get(view.buffer().index())
+                                .and_then(|buffer| {
+                                    buffer.get(view.offset()..view.offset() + view.length())
+                                })
+                                .map(<[u8]>::to_vec)
+                                .ok_or(ModelError::Truncated),
+                            gltf_image::Source::Uri { uri, .. } => read_uri(dir, uri),
+                        };
+                        mesh.add_texture(image.name().unwrap_or("embedded"), bytes)
+                    });
+                    let uvs = reader
+                        .read_tex_coords(info.tex_coord())
+                        .map(|uvs| uvs.into_f32().map(Vec2::from).collect::<Vec<_>>());
+                    (texture, uvs)
+                }
+                None => (None, None),
+            };
+            let uvs = uvs.unwrap_or_else(|| vec![Vec2::ZERO; positions.len()]);
+
+            let indices = reader.read_indices().map_or_else(
+                || (0..positions.len() as u32).collect(),
+                |indices| indices.into_u32().collect::<Vec<_>>(),
+            );
+            if mesh.triangles.len() + indices.len() / 3 > MAX_TRIANGLES {
+                return Err(ModelError::Invalid("too many triangles"));
+            }
+            for triangle in indices.chunks_exact(3) {
+                let triangle = [0, 1, 2].map(|i| triangle[i] as usize);
+                if triangle
+                    .iter()
+                    .any(|i| *i >= positions.len() || *i >= colors.len() || *i >= uvs.len())
+                {
+                    return Err(ModelError::Invalid("index is out of range"));
+                }
+
+                mesh.triangles.push(Triangle {
+                    positions: triangle.map(|i| positions[i]),
+                    colors: triangle.map(|i| colors[i] * color),
+                    uvs: triangle.map(|i| uvs[i]),
+                    texture,
+                });
+            }
+        }
+    }
+
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_cyclic_nodes() {
+        let json = r#"{
+            "asset": { "version": "2.0" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "children": [0] }]
+        }"#;
+        let gltf = Gltf::from_slice(json.as_bytes()).unwrap();
+        assert!(gltf_mesh(&gltf, Path::new("")).is_err());
+    }
+
+    #[test]
+    fn reads_obj_polygons() {
+        let obj = "v 0 0 0\nv 1 0 0 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nf 1/1 2/1 3/1 -1/1\n";
+        let mesh = read_obj(obj, Path::new("")).unwrap();
+        assert_eq!(mesh.triangles.len(), 2);
+        assert_eq!(mesh.triangles[1].positions[2], Vec3::Y);
+        assert_eq!(mesh.triangles[0].colors[1], Vec4::new(1., 0., 0., 1.));
+        assert_eq!(mesh.triangles[0].uvs[0], Vec2::new(0., 1.));
+
+        assert!(read_obj("v 0 0 0\nf 1 2 3\n", Path::new("")).is_err());
+    }
+
+    #[test]
+    fn reads_binary_and_ascii_stl() {
+        let mut binary = vec![0; 80];
+        binary.extend(1u32.to_le_bytes());
+        for value in [0., 0., 1., 0., 0., 0., 1., 0., 0., 0., 1., 0.] {
+            binary.extend(f32::to_le_bytes(value));
+        }
+        binary.extend([0; 2]);
+
+        let ascii = "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+            vertex 0 1 0\nendloop\nendfacet\nendsolid t\n";
+        for bytes in [&binary[..], ascii.as_bytes()] {
+            let mesh = read_stl(bytes).unwrap();
+            assert_eq!(mesh.triangles.len(), 1);
+            assert_eq!(
+                mesh.triangles[0].positions,
+                [Vec3::ZERO, Vec3::X, Vec3::new(0., 0., -1.)]
+            );
+        }
+
+        assert!(read_stl(&ascii.as_bytes()[..60]).is_err());
+    }
+}
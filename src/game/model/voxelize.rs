@@ -0,0 +1,257 @@
+use bevy::{prelude::*, utils::HashMap};
+use serde::Deserialize;
+
+use crate::game::vox::Vox;
+
+use super::{
+    load_ron_or_default,
+    triangles::{Triangle, TriangleMesh},
+    VoxModel,
+};
+
+const SETTINGS_PATH: &str = "assets/models/voxelize.ron";
+const DEFAULT_SETTINGS: &str = include_str!("../../../assets/models/voxelize.ron");
+const MAX_RESOLUTION: u32 = 256;
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum Fill {
+    Solid,
+    Shell,
+}
+
+#[derive(Deserialize)]
+pub struct VoxelizeSettings {
+    // Voxes along the mesh's longest side
+    pub resolution: u32,
+    pub fill: Fill,
+}
+
+impl VoxelizeSettings {
+    pub fn load() -> Self {
+        load_ron_or_default(SETTINGS_PATH, DEFAULT_SETTINGS, |_| Ok(()))
+    }
+}
+
+// Separating axis test between a triangle and the unit box centered on the origin
+fn overlaps(positions: [Vec3; 3]) -> bool {
+    let edges = [0, 1, 2].map(|i| positions[(i + 1) % 3] - positions[i]);
+    let crosses = edges
+        .into_iter()
+        .flat_map(|edge| [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| axis.cross(edge)));
+
+    [Vec3::X, Vec3::Y, Vec3::Z, edges[0].cross(edges[1])]
+        .into_iter()
+        .chain(crosses)
+        .all(|axis| {
+            let radius = 0.5 * axis.abs().dot(Vec3::ONE);
+            let projected = positions.map(|pos| pos.dot(axis));
+            let min = projected.into_iter().fold(f32::MAX, f32::min);
+            let max = projected.into_iter().fold(f32::MIN, f32::max);
+            min <= radius && max >= -radius
+        })
+}
+
+// Barycentric weights of the point in the triangle's plane, clamped to the triangle. Not quite
+// the closest point, but close enough to pick colors with.
+fn weights(positions: [Vec3; 3], pos: Vec3) -> Vec3 {
+    let [a, b, c] = positions;
+    let (ab, ac, ap) = (b - a, c - a, pos - a);
+    let (d00, d01, d11) = (ab.dot(ab), ab.dot(ac), ac.dot(ac));
+    let denom = d00 * d11 - d01 * d01;
+    if denom.abs() <= f32::EPSILON {
+        return Vec3::splat(1. / 3.);
+    }
+
+    let v = (d11 * ap.dot(ab) - d01 * ap.dot(ac)) / denom;
+    let w = (d00 * ap.dot(ac) - d01 * ap.dot(ab)) / denom;
+    let weights = Vec3::new(1. - v - w, v, w).max(Vec3::ZERO);
+    weights / weights.dot(Vec3::ONE)
+}
+
+fn sample(mesh: &TriangleMesh, triangle: &Triangle, weights: Vec3) -> Vec4 {
+    let mut color = Vec4::ZERO;
+    let mut uv = Vec2::ZERO;
+    for i in 0..3 {
+        color += triangle.colors[i] * weights[i];
+        uv += triangle.uvs[i] * weights[i];
+    }
+
+    match triangle.texture {
+        Some(texture) => color * mesh.textures[texture].sample(uv),
+        None => color,
+    }
+}
+
+// Scales the mesh so its longest side spans the resolution, then keeps every vox a triangle
+// touches, colored by the closest one
+fn surface(mesh: &TriangleMesh, resolution: u32) -> (IVec3, HashMap<IVec3, Vec4>) {
+    let (min, max) = mesh
+        .triangles
+        .iter()
+        .flat_map(|triangle| triangle.positions)
+        .fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), pos| (min.min(pos), max.max(pos)),
+        );
+    let extent = (max - min).max(Vec3::ZERO);
+    let scale = match extent.max_element() {
+        longest if longest > 0. => resolution as f32 / longest,
+        _ => 1.,
+    };
+    let size = (extent * scale)
+        .ceil()
+        .as_ivec3()
+        .clamp(IVec3::ONE, IVec3::splat(resolution as i32));
+
+    let mut closest = HashMap::<IVec3, (f32, Vec4)>::default();
+    for triangle in &mesh.triangles {
+        let positions = triangle.positions.map(|pos| (pos - min) * scale);
+        let low = positions.into_iter().reduce(Vec3::min).unwrap().floor();
+        let high = positions.into_iter().reduce(Vec3::max).unwrap().floor();
+        let low = low.as_ivec3().clamp(IVec3::ZERO, size - IVec3::ONE);
+        let high = high.as_ivec3().clamp(IVec3::ZERO, size - IVec3::ONE);
+
+        for z in low.z..=high.z {
+            for y in low.y..=high.y {
+                for x in low.x..=high.x {
+                    let pos = IVec3::new(x, y, z);
+                    let center = pos.as_vec3() + 0.5;
+                    if !overlaps(positions.map(|pos| pos - center)) {
+                        continue;
+                    }
+
+                    let weights = weights(positions, center);
+                    let point = positions[0] * weights.x
+                        + positions[1] * weights.y
+                        + positions[2] * weights.z;
+                    let distance = point.distance_squared(center);
+                    if closest
+                        .get(&pos)
+                        .is_some_and(|(other, _)| *other <= distance)
+                    {
+                        continue;
+                    }
+                    closest.insert(pos, (distance, sample(mesh, triangle, weights)));
+                }
+            }
+        }
+    }
+
+    let colors = closest
+        .into_iter()
+        .map(|(pos, (_, color))| (pos, color))
+        .collect();
+    (size, colors)
+}
+
+// Everything the outside can't reach is inside, so open meshes end up as shells
+fn fill(size: IVec3, surface: &HashMap<IVec3, Vec4>) -> Vec<(IVec3, Vec4)> {
+    let padded = size + 2;
+    let index = |pos: IVec3| {
+        let pos = pos + 1;
+        (pos.x + pos.y * padded.x + pos.z * padded.x * padded.y) as usize
+    };
+    let mut outside = vec![false; (padded.x * padded.y * padded.z) as usize];
+    let mut stack = vec![-IVec3::ONE];
+    outside[index(-IVec3::ONE)] = true;
+    while let Some(pos) = stack.pop() {
+        for axis in 0..3 {
+            for sign in [1, -1] {
+                let mut neighbor = pos;
+                neighbor[axis] += sign;
+                if neighbor.cmplt(-IVec3::ONE).any()
+                    || neighbor.cmpgt(size).any()
+                    || outside[index(neighbor)]
+                    || surface.contains_key(&neighbor)
+                {
+                    continue;
+                }
+
+                outside[index(neighbor)] = true;
+                stack.push(neighbor);
+            }
+        }
+    }
+
+    // The inside takes the color of the surface before it along X
+    let mut voxes = Vec::new();
+    for z in 0..size.z {
+        for y in 0..size.y {
+            let mut color = None;
+            for x in 0..size.x {
+                let pos = IVec3::new(x, y, z);
+                if let Some(surface) = surface.get(&pos) {
+                    color = Some(*surface);
+                    voxes.push((pos, *surface));
+                } else if let Some(color) = color.filter(|_| !outside[index(pos)]) {
+                    voxes.push((pos, color));
+                }
+            }
+        }
+    }
+
+    voxes
+}
+
+pub fn voxelize(mesh: &TriangleMesh, settings: &VoxelizeSettings) -> VoxModel {
+    let resolution = settings.resolution.clamp(1, MAX_RESOLUTION);
+    let (size, surface) = surface(mesh, resolution);
+    let voxes = match settings.fill {
+        Fill::Solid => fill(size, &surface),
+        Fill::Shell => surface.into_iter().collect(),
+    };
+
+    VoxModel::new(
+        voxes
+            .into_iter()
+            .map(|(pos, color)| (pos, Vox::solid(Color::rgb(color.x, color.y, color.z))))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::game::model::{
+        mesh::{write_obj, Surface},
+        triangles::read_obj,
+    };
+
+    fn round_trip(model: &VoxModel, fill: Fill) -> VoxModel {
+        let mesh = read_obj(&write_obj(&Surface::new(model)), Path::new("")).unwrap();
+        let resolution = model.size.max_element() as u32;
+        voxelize(&mesh, &VoxelizeSettings { resolution, fill })
+    }
+
+    #[test]
+    fn fills_closed_meshes() {
+        let cube = (0..64)
+            .map(|i| (IVec3::new(i % 4, i / 4 % 4, i / 16), Vox::solid(Color::RED)))
+            .collect();
+        let cube = VoxModel::new(cube);
+
+        let solid = round_trip(&cube, Fill::Solid);
+        assert_eq!(solid.size, cube.size);
+        assert_eq!(solid.voxes.len(), 64);
+        assert_eq!(round_trip(&cube, Fill::Shell).voxes.len(), 56);
+    }
+
+    #[test]
+    fn keeps_vertex_colors() {
+        let bar = VoxModel::new(vec![
+            (IVec3::new(0, 0, 0), Vox::solid(Color::RED)),
+            (IVec3::new(1, 0, 0), Vox::solid(Color::RED)),
+            (IVec3::new(2, 0, 0), Vox::solid(Color::BLUE)),
+        ]);
+
+        let mut voxes = round_trip(&bar, Fill::Solid)
+            .voxes
+            .into_iter()
+            .map(|(pos, vox)| (pos.x, vox.color))
+            .collect::<Vec<_>>();
+        voxes.sort_unstable_by_key(|(x, _)| *x);
+        assert_eq!(voxes, [(0, Color::RED), (1, Color::RED), (2, Color::BLUE)]);
+    }
+}
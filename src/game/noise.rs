@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+
+const LACUNARITY: f32 = 2.;
+const GAIN: f32 = 0.5;
+
+#[derive(Clone, Copy)]
+pub struct Noise {
+    seed: u64,
+}
+
+pub fn hash(seed: u64, pos: IVec3) -> u64 {
+    let mut hash = seed
+        ^ (pos.x as u32 as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (pos.y as u32 as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f)
+        ^ (pos.z as u32 as u64).wrapping_mul(0x1656_67b1_9e37_79f9);
+
+    // splitmix64 finalizer
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    pub fn with_offset(self, offset: u64) -> Self {
+        Self::new(hash(self.seed, IVec3::splat(offset as i32)))
+    }
+
+    fn grad2(&self, cell: IVec2, offset: Vec2) -> f32 {
+        let angle = (hash(self.seed, cell.extend(0)) >> 40) as f32 / (1 << 24) as f32
+            * std::f32::consts::TAU;
+        Vec2::new(angle.cos(), angle.sin()).dot(offset)
+    }
+
+    fn grad3(&self, cell: IVec3, offset: Vec3) -> f32 {
+        match hash(self.seed, cell) % 12 {
+            0 => offset.x + offset.y,
+            1 => -offset.x + offset.y,
+            2 => offset.x - offset.y,
+            3 => -offset.x - offset.y,
+            4 => offset.x + offset.z,
+            5 => -offset.x + offset.z,
+            6 => offset.x - offset.z,
+            7 => -offset.x - offset.z,
+            8 => offset.y + offset.z,
+            9 => -offset.y + offset.z,
+            10 => offset.y - offset.z,
+            _ => -offset.y - offset.z,
+        }
+    }
+
+    pub fn get2(&self, pos: Vec2) -> f32 {
+        let cell = pos.floor();
+        let local = pos - cell;
+        let cell = cell.as_ivec2();
+        let (u, v) = (fade(local.x), fade(local.y));
+
+        let corner = |x, y| {
+            self.grad2(
+                cell + IVec2::new(x, y),
+                local - Vec2::new(x as f32, y as f32),
+            )
+        };
+
+        lerp(
+            lerp(corner(0, 0), corner(1, 0), u),
+            lerp(corner(0, 1), corner(1, 1), u),
+            v,
+        ) * std::f32::consts::SQRT_2
+    }
+
+    pub fn get3(&self, pos: Vec3) -> f32 {
+        let cell = pos.floor();
+        let local = pos - cell;
+        let cell = cell.as_ivec3();
+        let (u, v, w) = (fade(local.x), fade(local.y), fade(local.z));
+
+        let corner = |x, y, z| {
+            self.grad3(
+                cell + IVec3::new(x, y, z),
+                local - Vec3::new(x as f32, y as f32, z as f32),
+            )
+        };
+
+        lerp(
+            lerp(
+                lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+                lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+                v,
+            ),
+            lerp(
+                lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+                lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+                v,
+            ),
+            w,
+        )
+    }
+
+    pub fn fbm2(&self, pos: Vec2, octaves: u32) -> f32 {
+        let (mut sum, mut amp, mut freq, mut norm) = (0., 1., 1., 0.);
+        for octave in 0..octaves {
+            sum += self.with_offset(octave as u64).get2(pos * freq) * amp;
+            norm += amp;
+            amp *= GAIN;
+            freq *= LACUNARITY;
+        }
+
+        sum / norm
+    }
+
+    pub fn fbm3(&self, pos: Vec3, octaves: u32) -> f32 {
+        let (mut sum, mut amp, mut freq, mut norm) = (0., 1., 1., 0.);
+        for octave in 0..octaves {
+            sum += self.with_offset(octave as u64).get3(pos * freq) * amp;
+            norm += amp;
+            amp *= GAIN;
+            freq *= LACUNARITY;
+        }
+
+        sum / norm
+    }
+}
@@ -143,8 +143,11 @@ impl<P: PhaseItem> RenderCommand<P> for SetVoxesPipeline {
 #[derive(Component, Deref)]
 struct GpuVoxesBindGroup(BindGroup);
 
-#[derive(Component)]
-struct BindGroupMarker;
+#[derive(Clone, Copy, Component)]
+enum BindGroupMarker {
+    Opaque,
+    Liquid,
+}
 
 struct SetGpuVoxesBindGroup<const I: usize>;
 
@@ -167,13 +170,13 @@ impl<const I: usize> EntityRenderCommand for SetGpuVoxesBindGroup<I> {
 struct DrawVertexPulledVoxes;
 
 impl EntityRenderCommand for DrawVertexPulledVoxes {
-    type Param = SRes<GpuVoxes>;
+    type Param = (SRes<GpuVoxes>, SQuery<Read<BindGroupMarker>>);
 
     #[inline]
     fn render<'w>(
         _: Entity,
-        _: Entity,
-        gpu_voxes: SystemParamItem<'w, '_, Self::Param>,
+        item: Entity,
+        (gpu_voxes, markers): SystemParamItem<'w, '_, Self::Param>,
         pass: &mut TrackedRenderPass<'w>,
     ) -> RenderCommandResult {
         let gpu_voxes = gpu_voxes.into_inner();
@@ -182,7 +185,8 @@ impl EntityRenderCommand for DrawVertexPulledVoxes {
             0,
             IndexFormat::Uint32,
         );
-        pass.draw_indexed(0..gpu_voxes.i_count as u32, 0, 0..1);
+        let i_count = gpu_voxes.insts(*markers.get(item).unwrap()).len() * VOX_I_COUNT;
+        pass.draw_indexed(0..i_count as u32, 0, 0..1);
 
         RenderCommandResult::Success
     }
@@ -259,7 +263,7 @@ impl FromWorld for VoxesPipeline {
                         entry_point: "fragment".into(),
                         targets: vec![ColorTargetState {
                             format: TextureFormat::bevy_default(),
-                            blend: Some(BlendState::REPLACE),
+                            blend: Some(BlendState::ALPHA_BLENDING),
                             write_mask: ColorWrites::ALL,
                         }],
                     }),
@@ -311,16 +315,25 @@ pub struct GpuVox {
 
 struct GpuVoxes {
     i_buffer: Option<Buffer>,
-    i_count: u32,
     insts: VoxBuffer,
+    liquid_insts: VoxBuffer,
 }
 
 impl Default for GpuVoxes {
     fn default() -> Self {
         Self {
             i_buffer: None,
-            i_count: 0,
             insts: VoxBuffer::new(BufferUsages::STORAGE),
+            liquid_insts: VoxBuffer::new(BufferUsages::STORAGE),
+        }
+    }
+}
+
+impl GpuVoxes {
+    fn insts(&self, marker: BindGroupMarker) -> &VoxBuffer {
+        match marker {
+            BindGroupMarker::Opaque => &self.insts,
+            BindGroupMarker::Liquid => &self.liquid_insts,
         }
     }
 }
@@ -335,20 +348,31 @@ impl RenderChunk {
     fn prepare(&self, gpu_voxes: &mut GpuVoxes) {
         let vox_pos = self.pos * CHUNK_SIZE as i32;
 
-        gpu_voxes.insts.insert(
-            self.pos,
-            self.voxes
-                .iter()
-                .enumerate()
-                .filter_map(|(i, vox)| {
-                    vox.as_ref().and_then(|vox| {
-                        vox.visible.then(|| GpuVox {
-                            pos: (vox_pos + (Chunk::expand(i))).as_vec3().extend(1.),
-                            color: vox.color.as_rgba_f32(),
-                        })
+        let (liquid_insts, insts) = self
+            .voxes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, vox)| {
+                vox.as_ref().and_then(|vox| {
+                    vox.visible.then(|| {
+                        (
+                            vox.material.is_liquid(),
+                            GpuVox {
+                                pos: (vox_pos + (Chunk::expand(i))).as_vec3().extend(1.),
+                                color: (vox.color * vox.light).as_rgba_f32(),
+                            },
+                        )
                     })
                 })
-                .collect(),
+            })
+            .partition::<Vec<_>, _>(|(liquid, _)| *liquid);
+
+        gpu_voxes
+            .insts
+            .insert(self.pos, insts.into_iter().map(|(_, inst)| inst).collect());
+        gpu_voxes.liquid_insts.insert(
+            self.pos,
+            liquid_insts.into_iter().map(|(_, inst)| inst).collect(),
         );
     }
 }
@@ -430,16 +454,17 @@ impl Node for VoxesPassNode {
 }
 
 fn init_bind_group(mut commands: Commands) {
-    commands.spawn().insert(BindGroupMarker);
+    commands.spawn().insert(BindGroupMarker::Opaque);
+    commands.spawn().insert(BindGroupMarker::Liquid);
 }
 
 fn extract_voxes_phase(
     mut commands: Commands,
     cams: Query<Entity, With<Camera3d>>,
-    bind_groups: Query<Entity, With<BindGroupMarker>>,
+    bind_groups: Query<(Entity, &BindGroupMarker)>,
 ) {
-    for bind_group_e in bind_groups.iter() {
-        commands.get_or_spawn(bind_group_e).insert(BindGroupMarker);
+    for (bind_group_e, marker) in bind_groups.iter() {
+        commands.get_or_spawn(bind_group_e).insert(*marker);
     }
 
     if let Ok(cam_e) = cams.get_single() {
@@ -495,18 +520,18 @@ fn prepare_voxes(
     if !chunks.is_empty() || !removed_chunks.is_empty() {
         for removed_chunk in removed_chunks.iter() {
             gpu_voxes.insts.remove(*removed_chunk);
+            gpu_voxes.liquid_insts.remove(*removed_chunk);
         }
 
         for chunk in chunks.iter() {
             chunk.prepare(&mut gpu_voxes);
         }
 
-        gpu_voxes.i_count = gpu_voxes.insts.len() as u32 * VOX_I_COUNT as u32;
-
+        let vox_count = gpu_voxes.insts.len().max(gpu_voxes.liquid_insts.len());
         gpu_voxes.i_buffer = Some(
             render_device.create_buffer_with_data(&BufferInitDescriptor {
                 label: Some("gpu_voxes_i_buffer"),
-                contents: cast_slice(&gen_i_buffer_data(gpu_voxes.insts.len())),
+                contents: cast_slice(&gen_i_buffer_data(vox_count)),
                 usage: BufferUsages::INDEX,
             }),
         );
@@ -514,41 +539,48 @@ fn prepare_voxes(
         gpu_voxes
             .insts
             .write_buffer(&*render_device, &*render_queue);
+        gpu_voxes
+            .liquid_insts
+            .write_buffer(&render_device, &render_queue);
     }
 }
 
 fn queue_voxes(
     mut commands: Commands,
-    bind_groups: Query<Entity, With<BindGroupMarker>>,
+    bind_groups: Query<(Entity, &BindGroupMarker)>,
     mut views: Query<&mut RenderPhase<VoxesPhaseItem>>,
     opaque_3d_draw_fns: Res<DrawFunctions<VoxesPhaseItem>>,
     voxes_pipeline: Res<VoxesPipeline>,
     render_device: Res<RenderDevice>,
     gpu_voxes: Res<GpuVoxes>,
 ) {
-    if gpu_voxes.insts.buffer().is_none() {
-        return;
-    }
-
     let draw_voxes = opaque_3d_draw_fns.read().get_id::<DrawVoxes>().unwrap();
 
+    let mut bind_groups = bind_groups.iter().collect::<Vec<_>>();
+    bind_groups.sort_by_key(|(_, marker)| **marker as u8);
+
     for mut opaque_phase in views.iter_mut() {
-        for bind_group_e in bind_groups.iter() {
+        for (bind_group_e, marker) in &bind_groups {
+            let buffer = match gpu_voxes.insts(**marker).buffer() {
+                Some(buffer) => buffer,
+                None => continue,
+            };
+
             commands
-                .get_or_spawn(bind_group_e)
+                .get_or_spawn(*bind_group_e)
                 .insert(GpuVoxesBindGroup(render_device.create_bind_group(
                     &BindGroupDescriptor {
                         label: Some("gpu_voxes_bind_group"),
                         layout: &voxes_pipeline.layout,
                         entries: &[BindGroupEntry {
                             binding: 0,
-                            resource: gpu_voxes.insts.buffer().unwrap().as_entire_binding(),
+                            resource: buffer.as_entire_binding(),
                         }],
                     },
                 )));
 
             opaque_phase.add(VoxesPhaseItem {
-                e: bind_group_e,
+                e: *bind_group_e,
                 draw_fn: draw_voxes,
             });
         }
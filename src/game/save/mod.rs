@@ -0,0 +1,821 @@
+mod snapshot;
+
+use std::{
+    cmp::Reverse,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    chunk::{Chunk, CHUNK_SIZE, CHUNK_VOLUME},
+    codec,
+    migrate::{legacy_chunk_size, legacy_format, migrate, OpenWorldError, WORLD_FORMAT},
+    vox::Vox,
+};
+
+const SAVES_DIR: &str = "saves";
+const META_FILE: &str = "world.ron";
+const REGIONS_DIR: &str = "regions";
+const OBJECTS_DIR: &str = "objects";
+const JOURNAL_FILE: &str = "journal.bin";
+const JOURNAL_MAGIC: &[u8; 4] = b"VXJL";
+const JOURNAL_ENTRY_HEADER_LEN: usize = 16;
+
+const REGION_SIZE: i32 = 8;
+const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+// Region files map each of their chunks to the hash of its contents, and each distinct chunk is
+// stored once under `objects`, so snapshots share chunks with the live world
+const REGION_MAGIC: &[u8; 4] = b"VXRH";
+const REGION_LEN: usize = REGION_MAGIC.len() + REGION_VOLUME * 8;
+// Before format 3, region files held their chunks after a table of offsets and lengths
+const LEGACY_REGION_MAGIC: &[u8; 4] = b"VXRG";
+const LEGACY_REGION_HEADER_LEN: usize = LEGACY_REGION_MAGIC.len() + REGION_VOLUME * 8;
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PlayerMeta {
+    pub position: Vec3,
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct WorldMeta {
+    #[serde(default = "legacy_format")]
+    pub format: u32,
+    #[serde(default = "legacy_chunk_size")]
+    pub chunk_size: u32,
+    pub name: String,
+    pub seed: u64,
+    pub generator: String,
+    pub created: u64,
+    pub last_played: u64,
+    pub player: Option<PlayerMeta>,
+}
+
+impl WorldMeta {
+    pub fn new(name: String, seed: u64, generator: String) -> Self {
+        Self {
+            format: WORLD_FORMAT,
+            chunk_size: CHUNK_SIZE as u32,
+            name,
+            seed,
+            generator,
+            created: now(),
+            last_played: now(),
+            player: None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WorldSave {
+    dir: PathBuf,
+}
+
+// A copy of a chunk's voxes, taken so it can be saved off the main thread
+pub type ChunkSnapshot = Arc<[Option<Vox>]>;
+
+#[derive(Clone)]
+pub struct CurrentWorld {
+    pub save: WorldSave,
+    pub meta: WorldMeta,
+}
+
+// Seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+fn slug(name: &str) -> String {
+    let slug = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() {
+        "world".to_string()
+    } else {
+        slug
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn unused_name(name: &str) -> String {
+    let names = WorldSave::list()
+        .into_iter()
+        .map(|(_, meta)| meta.name)
+        .collect::<Vec<_>>();
+
+    (1..)
+        .map(|i| match i {
+            1 => name.to_string(),
+            _ => format!("{} {}", name, i),
+        })
+        .find(|name| !names.contains(name))
+        .unwrap()
+}
+
+fn region_pos(pos: IVec3) -> IVec3 {
+    (pos.as_vec3() / REGION_SIZE as f32).floor().as_ivec3()
+}
+
+fn region_file_name(region: IVec3) -> String {
+    format!("r.{}.{}.{}.bin", region.x, region.y, region.z)
+}
+
+fn parse_region_file_name(name: &str) -> Option<IVec3> {
+    let coords = name
+        .strip_prefix("r.")?
+        .strip_suffix(".bin")?
+        .split('.')
+        .map(|coord| coord.parse().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    match coords[..] {
+        [x, y, z] => Some(IVec3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+fn region_files(dir: &Path) -> io::Result<Vec<(IVec3, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::default()),
+        Err(err) => return Err(err),
+    };
+
+    let mut regions = Vec::default();
+    for entry in entries {
+        let path = entry?.path();
+        if let Some(region) = path
+            .file_name()
+            .and_then(|name| parse_region_file_name(&name.to_string_lossy()))
+        {
+            regions.push((region, path));
+        }
+    }
+
+    Ok(regions)
+}
+
+fn region_index(pos: IVec3) -> usize {
+    let local = pos - region_pos(pos) * REGION_SIZE;
+    (local.x + local.y * REGION_SIZE + local.z * REGION_SIZE * REGION_SIZE) as usize
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Writes to a temporary file first, so a crash leaves either the old file or the new one
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+
+    // Not every platform can sync a directory, and the rename is still atomic without it
+    if let Some(dir) = path.parent() {
+        File::open(dir).and_then(|dir| dir.sync_all()).ok();
+    }
+
+    Ok(())
+}
+
+fn decode_chunk(bytes: &[u8]) -> io::Result<Chunk> {
+    let voxes = codec::decode(bytes).map_err(|err| invalid(&err.to_string()))?;
+    if voxes.len() != CHUNK_VOLUME {
+        return Err(invalid("wrong number of voxes"));
+    }
+
+    Ok(Chunk::loaded(voxes))
+}
+
+// Empty entries are zero, which `store_object` never hands out
+fn read_region(path: &Path) -> io::Result<HashMap<usize, u64>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(default()),
+        Err(err) => return Err(err),
+    };
+
+    if bytes.len() != REGION_LEN || &bytes[..REGION_MAGIC.len()] != REGION_MAGIC {
+        return Err(invalid("not a region file"));
+    }
+
+    Ok(bytes[REGION_MAGIC.len()..]
+        .chunks_exact(8)
+        .map(|entry| u64::from_le_bytes(entry.try_into().unwrap()))
+        .enumerate()
+        .filter(|(_, hash)| *hash != 0)
+        .collect())
+}
+
+// Reads just the one entry from the region file, for loading chunks one at a time
+fn read_region_entry(path: &Path, index: usize) -> io::Result<Option<u64>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let truncated = |err: io::Error| match err.kind() {
+        io::ErrorKind::UnexpectedEof => invalid("not a region file"),
+        _ => err,
+    };
+
+    let mut magic = [0; REGION_MAGIC.len()];
+    file.read_exact(&mut magic).map_err(truncated)?;
+    if &magic != REGION_MAGIC {
+        return Err(invalid("not a region file"));
+    }
+
+    let mut entry = [0; 8];
+    file.seek(SeekFrom::Start((REGION_MAGIC.len() + index * 8) as u64))?;
+    file.read_exact(&mut entry).map_err(truncated)?;
+    let hash = u64::from_le_bytes(entry);
+    Ok((hash != 0).then_some(hash))
+}
+
+fn write_region(path: &Path, chunks: &HashMap<usize, u64>) -> io::Result<()> {
+    let mut bytes = REGION_MAGIC.to_vec();
+    for i in 0..REGION_VOLUME {
+        bytes.extend(chunks.get(&i).copied().unwrap_or(0).to_le_bytes());
+    }
+
+    write_atomic(path, &bytes)
+}
+
+fn read_legacy_region(bytes: &[u8]) -> io::Result<HashMap<usize, Vec<u8>>> {
+    if bytes.len() < LEGACY_REGION_HEADER_LEN {
+        return Err(invalid("not a region file"));
+    }
+
+    let mut chunks = HashMap::default();
+    for i in 0..REGION_VOLUME {
+        let entry = LEGACY_REGION_MAGIC.len() + i * 8;
+        let offset = u32::from_le_bytes(bytes[entry..entry + 4].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(bytes[entry + 4..entry + 8].try_into().unwrap()) as usize;
+        if len == 0 {
+            continue;
+        }
+
+        let chunk = bytes
+            .get(offset..offset + len)
+            .ok_or_else(|| invalid("chunk out of bounds"))?;
+        chunks.insert(i, chunk.to_vec());
+    }
+
+    Ok(chunks)
+}
+
+fn encode_journal(chunks: &[(IVec3, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = JOURNAL_MAGIC.to_vec();
+    for (pos, chunk) in chunks {
+        for coord in pos.to_array() {
+            bytes.extend(coord.to_le_bytes());
+        }
+        bytes.extend((chunk.len() as u32).to_le_bytes());
+        bytes.extend(chunk);
+    }
+
+    bytes
+}
+
+fn decode_journal(bytes: &[u8]) -> io::Result<Vec<(IVec3, Vec<u8>)>> {
+    let mut rest = bytes
+        .strip_prefix(JOURNAL_MAGIC)
+        .ok_or_else(|| invalid("not a journal file"))?;
+
+    let mut chunks = Vec::default();
+    while !rest.is_empty() {
+        if rest.len() < JOURNAL_ENTRY_HEADER_LEN {
+            return Err(invalid("journal entry truncated"));
+        }
+
+        let word = |i: usize| rest[i * 4..i * 4 + 4].try_into().unwrap();
+        let pos = IVec3::new(
+            i32::from_le_bytes(word(0)),
+            i32::from_le_bytes(word(1)),
+            i32::from_le_bytes(word(2)),
+        );
+        let len = u32::from_le_bytes(word(3)) as usize;
+        let chunk = rest[JOURNAL_ENTRY_HEADER_LEN..]
+            .get(..len)
+            .ok_or_else(|| invalid("journal entry truncated"))?;
+
+        chunks.push((pos, chunk.to_vec()));
+        rest = &rest[JOURNAL_ENTRY_HEADER_LEN + len..];
+    }
+
+    Ok(chunks)
+}
+
+impl WorldSave {
+    // A new save directory named after `name` that no other world uses
+    pub fn unused(name: &str) -> Self {
+        let slug = slug(name);
+        (1..)
+            .map(|i| match i {
+                1 => Path::new(SAVES_DIR).join(&slug),
+                _ => Path::new(SAVES_DIR).join(format!("{}-{}", slug, i)),
+            })
+            .find(|dir| !dir.exists())
+            .map(|dir| Self { dir })
+            .unwrap()
+    }
+
+    // Every world in the saves directory, most recently played first
+    pub fn list() -> Vec<(Self, WorldMeta)> {
+        let entries = match fs::read_dir(SAVES_DIR) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::default(),
+        };
+
+        let mut worlds = entries
+            .filter_map(|entry| {
+                let save = Self {
+                    dir: entry.ok()?.path(),
+                };
+                match save.read_meta() {
+                    Ok(meta) => Some((save, meta)),
+                    Err(err) => {
+                        warn!("Skipping world {}: {}", save.dir.display(), err);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        worlds.sort_by_key(|(_, meta)| Reverse(meta.last_played));
+        worlds
+    }
+
+    fn region_path(&self, region: IVec3) -> PathBuf {
+        self.dir.join(REGIONS_DIR).join(region_file_name(region))
+    }
+
+    pub fn read_meta(&self) -> io::Result<WorldMeta> {
+        self.read_meta_as()
+    }
+
+    pub fn read_meta_as<T: DeserializeOwned>(&self) -> io::Result<T> {
+        ron::from_str(&fs::read_to_string(self.dir.join(META_FILE))?)
+            .map_err(|err| invalid(&err.to_string()))
+    }
+
+    // Upgrades the world to the current format and finishes any interrupted save
+    pub fn open(&self) -> Result<WorldMeta, OpenWorldError> {
+        migrate(self)?;
+        self.finish_restore()?;
+
+        match self.replay_journal() {
+            Ok(0) => (),
+            Ok(count) => info!("Replayed {} chunk writes from an interrupted save", count),
+            Err(err) => warn!("Failed to replay save journal: {}", err),
+        }
+
+        // Saves leave behind the chunks they replaced
+        if let Err(err) = self.collect_objects() {
+            warn!("Failed to remove unused chunks: {}", err);
+        }
+
+        Ok(self.read_meta()?)
+    }
+
+    pub fn write_meta(&self, meta: &WorldMeta) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(
+            &self.dir.join(META_FILE),
+            ron::ser::to_string_pretty(meta, default())
+                .map_err(|err| invalid(&err.to_string()))?
+                .as_bytes(),
+        )
+    }
+
+    // Rewriting metadata from a newer format could drop fields this version doesn't know about
+    fn editable_meta(&self) -> io::Result<WorldMeta> {
+        let meta = self.read_meta()?;
+        if meta.format > WORLD_FORMAT {
+            return Err(invalid(
+                &OpenWorldError::NewerFormat(meta.format).to_string(),
+            ));
+        }
+
+        Ok(meta)
+    }
+
+    pub fn rename(&self, name: &str) -> io::Result<()> {
+        let mut meta = self.editable_meta()?;
+        meta.name = name.to_string();
+        self.write_meta(&meta)
+    }
+
+    pub fn duplicate(&self) -> io::Result<Self> {
+        let mut meta = self.editable_meta()?;
+        meta.name = unused_name(&format!("{} (copy)", meta.name));
+        meta.created = now();
+
+        let copy = Self::unused(&meta.name);
+        copy_dir(&self.dir, &copy.dir)?;
+        copy.write_meta(&meta)?;
+        Ok(copy)
+    }
+
+    pub fn delete(&self) -> io::Result<()> {
+        fs::remove_dir_all(&self.dir)
+    }
+
+    fn object_path(&self, hash: u64) -> PathBuf {
+        self.dir.join(OBJECTS_DIR).join(format!("{:016x}", hash))
+    }
+
+    // Returns the hash the chunk is stored under, which is the same for identical chunks
+    fn store_object(&self, bytes: &[u8]) -> io::Result<u64> {
+        let hash = codec::fnv(bytes);
+        if hash == 0 {
+            return Err(invalid("chunk hashes to the empty region entry"));
+        }
+
+        let path = self.object_path(hash);
+        match fs::read(&path) {
+            Ok(stored) if stored == bytes => Ok(hash),
+            Ok(_) => Err(invalid(&format!("object {:016x} collides", hash))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                write_atomic(&path, bytes).map(|_| hash)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn load_chunk(&self, pos: IVec3) -> Option<Chunk> {
+        let path = self.region_path(region_pos(pos));
+        read_region_entry(&path, region_index(pos))
+            .and_then(|hash| {
+                hash.map(|hash| decode_chunk(&fs::read(self.object_path(hash))?))
+                    .transpose()
+            })
+            .unwrap_or_else(|err| {
+                warn!(
+                    "Failed to load chunk {} from {}: {}",
+                    pos,
+                    path.display(),
+                    err
+                );
+                None
+            })
+    }
+
+    // Chunks are journaled before any region is touched, so a save that's interrupted partway
+    // through is finished by `replay_journal` the next time the world is opened
+    pub fn save_chunks(&self, chunks: &[(IVec3, ChunkSnapshot)]) -> io::Result<()> {
+        if chunks.is_empty() {
+            return Ok(());
+        }
+
+        let chunks = chunks
+            .iter()
+            .map(|(pos, voxes)| (*pos, codec::encode(voxes, true)))
+            .collect::<Vec<_>>();
+
+        fs::create_dir_all(&self.dir)?;
+        let journal = self.dir.join(JOURNAL_FILE);
+        write_atomic(&journal, &encode_journal(&chunks))?;
+        self.write_regions(chunks)?;
+        fs::remove_file(journal)
+    }
+
+    // Returns how many chunk writes were replayed
+    pub fn replay_journal(&self) -> io::Result<usize> {
+        let journal = self.dir.join(JOURNAL_FILE);
+        let bytes = match fs::read(&journal) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+
+        let chunks = decode_journal(&bytes)?;
+        let count = chunks.len();
+        self.write_regions(chunks)?;
+        fs::remove_file(journal)?;
+        Ok(count)
+    }
+
+    fn write_regions(&self, chunks: Vec<(IVec3, Vec<u8>)>) -> io::Result<()> {
+        fs::create_dir_all(self.dir.join(OBJECTS_DIR))?;
+        let mut regions = HashMap::<_, Vec<_>>::default();
+        for (pos, chunk) in chunks {
+            regions
+                .entry(region_pos(pos))
+                .or_default()
+                .push((region_index(pos), self.store_object(&chunk)?));
+        }
+
+        fs::create_dir_all(self.dir.join(REGIONS_DIR))?;
+        for (region, chunks) in regions {
+            let path = self.region_path(region);
+            read_region(&path)
+                .and_then(|mut region_chunks| {
+                    region_chunks.extend(chunks);
+                    write_region(&path, &region_chunks)
+                })
+                .map_err(|err| {
+                    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+                })?;
+        }
+
+        Ok(())
+    }
+
+    // Format 3 moved chunks out of region files into the objects the world shares with its
+    // snapshots. Regions that were already moved are skipped, so this can run again if the game
+    // dies partway through.
+    pub fn move_chunks_to_objects(&self) -> io::Result<()> {
+        self.finish_restore()?;
+        fs::create_dir_all(self.dir.join(OBJECTS_DIR))?;
+        for (_, path) in region_files(&self.dir.join(REGIONS_DIR))? {
+            let bytes = fs::read(&path)?;
+            if !bytes.starts_with(LEGACY_REGION_MAGIC) {
+                continue;
+            }
+
+            let chunks = read_legacy_region(&bytes)?
+                .into_iter()
+                .map(|(index, chunk)| Ok((index, self.store_object(&chunk)?)))
+                .collect::<io::Result<_>>()?;
+            write_region(&path, &chunks)?;
+        }
+
+        self.move_snapshot_objects()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::temp_dir;
+
+    use super::*;
+
+    fn temp_save(name: &str) -> WorldSave {
+        let save = WorldSave {
+            dir: temp_dir().join(format!("voxmod-{}-{}", name, std::process::id())),
+        };
+        fs::remove_dir_all(&save.dir).ok();
+        save
+    }
+
+    fn snapshot(color: Color) -> ChunkSnapshot {
+        (0..CHUNK_VOLUME)
+            .map(|i| (i % 3 == 0).then(|| Vox::solid(color)))
+            .collect()
+    }
+
+    fn color_at(save: &WorldSave, pos: IVec3) -> Option<Color> {
+        save.load_chunk(pos)?
+            .voxes()
+            .iter()
+            .flatten()
+            .next()
+            .map(|vox| vox.color)
+    }
+
+    #[test]
+    fn saved_chunks_load() {
+        let save = temp_save("save");
+        let chunks = [
+            (IVec3::new(0, 0, 0), snapshot(Color::RED)),
+            (IVec3::new(-9, 3, 20), snapshot(Color::BLUE)),
+        ];
+
+        save.save_chunks(&chunks).unwrap();
+        assert!(!save.dir.join(JOURNAL_FILE).exists());
+        for (pos, voxes) in &chunks {
+            assert_eq!(
+                color_at(&save, *pos),
+                Some(voxes[0].as_ref().unwrap().color)
+            );
+        }
+        assert!(save.load_chunk(IVec3::new(1, 0, 0)).is_none());
+
+        save.delete().unwrap();
+    }
+
+    #[test]
+    fn edited_chunks_are_saved() {
+        let save = temp_save("edit");
+        let pos = IVec3::new(3, 1, -4);
+        let mut chunk = Chunk::new(snapshot(Color::RED).to_vec());
+        assert!(!chunk.is_modified());
+
+        let edited = IVec3::new(1, 2, 3);
+        chunk.edit(&[(edited, Vox::solid(Color::GREEN))]);
+        assert!(chunk.is_modified());
+        save.save_chunks(&[(pos, chunk.voxes().into())]).unwrap();
+
+        let loaded = save.load_chunk(pos).unwrap();
+        assert!(loaded.is_modified());
+        assert_eq!(loaded.get(edited).unwrap().color, Color::GREEN);
+        assert_eq!(loaded.voxes()[0].as_ref().unwrap().color, Color::RED);
+
+        save.delete().unwrap();
+    }
+
+    #[test]
+    fn truncated_regions_are_rejected() {
+        let save = temp_save("truncated-region");
+        let pos = IVec3::new(0, 0, 0);
+        save.save_chunks(&[(pos, snapshot(Color::RED))]).unwrap();
+
+        let path = save.region_path(region_pos(pos));
+        let bytes = fs::read(&path).unwrap();
+        for len in [2, REGION_MAGIC.len() + 4] {
+            fs::write(&path, &bytes[..len]).unwrap();
+            assert!(read_region(&path).is_err());
+            assert!(read_region_entry(&path, region_index(pos)).is_err());
+        }
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(read_region(&path).is_err());
+
+        save.delete().unwrap();
+    }
+
+    #[test]
+    fn interrupted_save_is_replayed() {
+        let save = temp_save("replay");
+        let pos = IVec3::new(2, -1, 5);
+        save.save_chunks(&[(pos, snapshot(Color::RED))]).unwrap();
+
+        // A save that died after writing its journal but before touching any region
+        write_atomic(
+            &save.dir.join(JOURNAL_FILE),
+            &encode_journal(&[(pos, codec::encode(&snapshot(Color::GREEN), true))]),
+        )
+        .unwrap();
+        assert_eq!(color_at(&save, pos), Some(Color::RED));
+
+        assert_eq!(save.replay_journal().unwrap(), 1);
+        assert_eq!(color_at(&save, pos), Some(Color::GREEN));
+        assert_eq!(save.replay_journal().unwrap(), 0);
+
+        save.delete().unwrap();
+    }
+
+    const LEGACY_META: &str = "(name: \"Old\", seed: 18446744073709551615, generator: \"noise\", \
+        created: 1, last_played: 2, player: None)";
+
+    #[test]
+    fn legacy_world_is_migrated() {
+        let save = temp_save("legacy");
+        fs::create_dir_all(&save.dir).unwrap();
+        fs::write(save.dir.join(META_FILE), LEGACY_META).unwrap();
+        assert_eq!(save.read_meta().unwrap().format, 1);
+
+        let meta = save.open().unwrap();
+        assert_eq!(meta.format, WORLD_FORMAT);
+        assert_eq!(meta.seed, u64::MAX);
+        assert_eq!(save.read_meta().unwrap().format, WORLD_FORMAT);
+
+        save.delete().unwrap();
+    }
+
+    #[test]
+    fn inline_regions_are_migrated() {
+        let save = temp_save("inline");
+        let mut meta = WorldMeta::new("Inline".to_string(), 0, "noise".to_string());
+        meta.format = 2;
+        save.write_meta(&meta).unwrap();
+
+        // A format 2 region holding its chunk inline, and a snapshot object using the same hash
+        let pos = IVec3::new(1, 2, 3);
+        let chunk = codec::encode(&snapshot(Color::RED), true);
+        let mut region = LEGACY_REGION_MAGIC.to_vec();
+        region.resize(LEGACY_REGION_HEADER_LEN, 0);
+        let entry = LEGACY_REGION_MAGIC.len() + region_index(pos) * 8;
+        region[entry..entry + 4].copy_from_slice(&(LEGACY_REGION_HEADER_LEN as u32).to_le_bytes());
+        region[entry + 4..entry + 8].copy_from_slice(&(chunk.len() as u32).to_le_bytes());
+        region.extend(&chunk);
+        fs::create_dir_all(save.dir.join(REGIONS_DIR)).unwrap();
+        fs::write(save.region_path(region_pos(pos)), region).unwrap();
+        let snapshot_objects = save.dir.join("snapshots/objects");
+        fs::create_dir_all(&snapshot_objects).unwrap();
+        fs::write(
+            snapshot_objects.join(format!("{:016x}", codec::fnv(&chunk))),
+            &chunk,
+        )
+        .unwrap();
+
+        assert_eq!(save.open().unwrap().format, WORLD_FORMAT);
+        assert_eq!(color_at(&save, pos), Some(Color::RED));
+        assert_eq!(object_count(&save), 1);
+        assert!(!snapshot_objects.exists());
+
+        save.delete().unwrap();
+    }
+
+    #[test]
+    fn newer_world_is_rejected() {
+        let save = temp_save("newer");
+        let mut meta = WorldMeta::new("New".to_string(), 0, "noise".to_string());
+        meta.format = WORLD_FORMAT + 1;
+        save.write_meta(&meta).unwrap();
+
+        assert!(matches!(
+            save.open(),
+            Err(OpenWorldError::NewerFormat(format)) if format == WORLD_FORMAT + 1
+        ));
+        assert!(save.rename("Renamed").is_err());
+        assert_eq!(save.read_meta().unwrap().name, "New");
+
+        save.delete().unwrap();
+    }
+
+    fn object_count(save: &WorldSave) -> usize {
+        fs::read_dir(save.dir.join(OBJECTS_DIR)).unwrap().count()
+    }
+
+    #[test]
+    fn snapshots_share_and_restore_chunks() {
+        let save = temp_save("snapshots");
+        save.write_meta(&WorldMeta::new(
+            "Snapshots".to_string(),
+            0,
+            "noise".to_string(),
+        ))
+        .unwrap();
+        let (a, b) = (IVec3::new(0, 0, 0), IVec3::new(20, 0, 0));
+        save.save_chunks(&[(a, snapshot(Color::RED)), (b, snapshot(Color::RED))])
+            .unwrap();
+
+        assert_eq!(object_count(&save), 1);
+        let first = save.create_snapshot("First").unwrap();
+        assert_eq!(object_count(&save), 1);
+
+        save.save_chunks(&[(b, snapshot(Color::BLUE))]).unwrap();
+        assert_eq!(object_count(&save), 2);
+        let second = save.create_snapshot("Second").unwrap();
+        assert_eq!(object_count(&save), 2);
+
+        let ids = save
+            .snapshots()
+            .into_iter()
+            .map(|snapshot| snapshot.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [second.id.clone(), first.id.clone()]);
+
+        save.restore_snapshot(&first.id).unwrap();
+        assert_eq!(color_at(&save, b), Some(Color::RED));
+
+        // A restore that died between swapping the region directories
+        save.save_chunks(&[(a, snapshot(Color::GREEN))]).unwrap();
+        fs::rename(save.dir.join(REGIONS_DIR), save.dir.join("regions.restore")).unwrap();
+        save.finish_restore().unwrap();
+        assert_eq!(color_at(&save, a), Some(Color::GREEN));
+
+        // The live world uses red and green, and the second snapshot blue
+        assert_eq!(save.prune_snapshots(None, Some(1)).unwrap(), 1);
+        assert_eq!(object_count(&save), 3);
+        save.delete_snapshot(&second.id).unwrap();
+        assert_eq!(object_count(&save), 2);
+        assert!(save.snapshots().is_empty());
+        assert_eq!(color_at(&save, b), Some(Color::RED));
+
+        save.delete().unwrap();
+    }
+
+    #[test]
+    fn truncated_journal_is_rejected() {
+        let journal = encode_journal(&[(IVec3::ONE, vec![1, 2, 3, 4])]);
+        assert_eq!(decode_journal(&journal).unwrap().len(), 1);
+        for len in 0..journal.len() {
+            if len != JOURNAL_MAGIC.len() {
+                assert!(decode_journal(&journal[..len]).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    invalid, now, read_region, region_file_name, region_files, slug, write_atomic, write_region,
+    PlayerMeta, WorldSave, JOURNAL_FILE, OBJECTS_DIR, REGIONS_DIR,
+};
+
+const SNAPSHOTS_DIR: &str = "snapshots";
+const RESTORE_DIR: &str = "regions.restore";
+const REPLACED_DIR: &str = "regions.replaced";
+
+// Snapshots list the same chunk hashes as the region files, so they only cost their manifest
+#[derive(Deserialize, Serialize)]
+struct Manifest {
+    name: String,
+    created: u64,
+    player: Option<PlayerMeta>,
+    regions: Vec<(IVec3, Vec<(usize, u64)>)>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Snapshot {
+    #[serde(skip)]
+    pub id: String,
+    pub name: String,
+    pub created: u64,
+}
+
+fn remove_dir_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+impl WorldSave {
+    fn snapshots_dir(&self) -> PathBuf {
+        self.dir.join(SNAPSHOTS_DIR)
+    }
+
+    fn manifest_path(&self, id: &str) -> PathBuf {
+        self.snapshots_dir().join(format!("{}.ron", id))
+    }
+
+    fn read_manifest<T: for<'de> Deserialize<'de>>(&self, id: &str) -> io::Result<T> {
+        ron::from_str(&fs::read_to_string(self.manifest_path(id))?)
+            .map_err(|err| invalid(&err.to_string()))
+    }
+
+    // Snapshots what's on disk, so an open world should be saved first
+    pub fn create_snapshot(&self, name: &str) -> io::Result<Snapshot> {
+        self.replay_journal()?;
+        fs::create_dir_all(self.snapshots_dir())?;
+
+        let mut regions = region_files(&self.dir.join(REGIONS_DIR))?
+            .into_iter()
+            .map(|(region, path)| {
+                let mut chunks = read_region(&path)?.into_iter().collect::<Vec<_>>();
+                chunks.sort_unstable();
+                Ok((region, chunks))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        regions.sort_by_key(|(region, _)| region.to_array());
+
+        let created = now();
+        let id = (1..)
+            .map(|i| match i {
+                1 => format!("{}-{}", created, slug(name)),
+                _ => format!("{}-{}-{}", created, slug(name), i),
+            })
+            .find(|id| !self.manifest_path(id).exists())
+            .unwrap();
+
+        let manifest = Manifest {
+            name: name.to_string(),
+            created,
+            player: self.read_meta()?.player,
+            regions,
+        };
+        write_atomic(
+            &self.manifest_path(&id),
+            ron::ser::to_string_pretty(&manifest, default())
+                .map_err(|err| invalid(&err.to_string()))?
+                .as_bytes(),
+        )?;
+
+        Ok(Snapshot {
+            id,
+            name: manifest.name,
+            created,
+        })
+    }
+
+    fn snapshot_ids(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(self.snapshots_dir()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::default()),
+            Err(err) => return Err(err),
+        };
+
+        let mut ids = Vec::default();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "ron") {
+                if let Some(id) = path.file_stem() {
+                    ids.push(id.to_string_lossy().into_owned());
+                }
+            }
+        }
+
+        Ok(ids)
+    }
+
+    // Newest first
+    pub fn snapshots(&self) -> Vec<Snapshot> {
+        let mut snapshots = self
+            .snapshot_ids()
+            .unwrap_or_else(|err| {
+                warn!("Failed to list snapshots: {}", err);
+                Vec::default()
+            })
+            .into_iter()
+            .filter_map(|id| match self.read_manifest::<Snapshot>(&id) {
+                Ok(snapshot) => Some(Snapshot { id, ..snapshot }),
+                Err(err) => {
+                    warn!("Skipping snapshot {}: {}", id, err);
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        snapshots.sort_by(|a, b| b.created.cmp(&a.created).then(b.id.cmp(&a.id)));
+        snapshots
+    }
+
+    // Only for worlds that aren't open
+    pub fn restore_snapshot(&self, id: &str) -> io::Result<()> {
+        let manifest = self.read_manifest::<Manifest>(id)?;
+        let regions = self.dir.join(REGIONS_DIR);
+        let restore = self.dir.join(RESTORE_DIR);
+        let replaced = self.dir.join(REPLACED_DIR);
+
+        fs::create_dir_all(&regions)?;
+        remove_dir_if_exists(&restore)?;
+        fs::create_dir_all(&restore)?;
+        for (region, chunks) in &manifest.regions {
+            for (_, hash) in chunks {
+                if !self.object_path(*hash).exists() {
+                    return Err(invalid(&format!("chunk {:016x} is missing", hash)));
+                }
+            }
+
+            let chunks = chunks.iter().copied().collect::<HashMap<_, _>>();
+            write_region(&restore.join(region_file_name(*region)), &chunks)?;
+        }
+
+        // Writes journaled before the restore belong to the world being replaced
+        match fs::remove_file(self.dir.join(JOURNAL_FILE)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+
+        remove_dir_if_exists(&replaced)?;
+        fs::rename(&regions, &replaced)?;
+        fs::rename(&restore, &regions)?;
+        fs::remove_dir_all(&replaced)?;
+
+        let mut meta = self.read_meta()?;
+        meta.player = manifest.player;
+        self.write_meta(&meta)
+    }
+
+    // Completes a restore that was interrupted while swapping region directories
+    pub(super) fn finish_restore(&self) -> io::Result<()> {
+        let regions = self.dir.join(REGIONS_DIR);
+        let restore = self.dir.join(RESTORE_DIR);
+        if !regions.exists() && restore.exists() {
+            fs::rename(&restore, &regions)?;
+        }
+
+        remove_dir_if_exists(&restore)?;
+        remove_dir_if_exists(&self.dir.join(REPLACED_DIR))
+    }
+
+    // Removes snapshots older than `max_age` seconds or beyond the newest `max_count`, along with
+    // any chunks no remaining snapshot uses. Returns how many snapshots were removed.
+    pub fn prune_snapshots(
+        &self,
+        max_age: Option<u64>,
+        max_count: Option<usize>,
+    ) -> io::Result<usize> {
+        let cutoff = max_age.map(|max_age| now().saturating_sub(max_age));
+        let mut pruned = 0;
+        for (i, snapshot) in self.snapshots().iter().enumerate() {
+            if max_count.is_some_and(|max_count| i >= max_count)
+                || cutoff.is_some_and(|cutoff| snapshot.created < cutoff)
+            {
+                fs::remove_file(self.manifest_path(&snapshot.id))?;
+                pruned += 1;
+            }
+        }
+
+        self.collect_objects()?;
+        Ok(pruned)
+    }
+
+    pub fn delete_snapshot(&self, id: &str) -> io::Result<()> {
+        fs::remove_file(self.manifest_path(id))?;
+        self.collect_objects()
+    }
+
+    // Removes the chunks neither the world nor any snapshot uses
+    pub(super) fn collect_objects(&self) -> io::Result<()> {
+        // Any unreadable manifest or region stops collection, since its chunks can't be told apart
+        let mut used = HashSet::default();
+        for id in self.snapshot_ids()? {
+            let manifest = self.read_manifest::<Manifest>(&id)?;
+            used.extend(
+                manifest
+                    .regions
+                    .into_iter()
+                    .flat_map(|(_, chunks)| chunks.into_iter().map(|(_, hash)| hash)),
+            );
+        }
+        for (_, path) in region_files(&self.dir.join(REGIONS_DIR))? {
+            used.extend(read_region(&path)?.into_iter().map(|(_, hash)| hash));
+        }
+
+        let entries = match fs::read_dir(self.dir.join(OBJECTS_DIR)) {
+            Ok(entries) => entries,
+            Err(_) => return Ok(()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let hash = path
+                .file_name()
+                .and_then(|name| u64::from_str_radix(&name.to_string_lossy(), 16).ok());
+            if hash.is_some_and(|hash| !used.contains(&hash)) {
+                fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Before format 3, snapshots kept their chunks to themselves under the same hashes
+    pub(super) fn move_snapshot_objects(&self) -> io::Result<()> {
+        let objects = self.snapshots_dir().join(OBJECTS_DIR);
+        let entries = match fs::read_dir(&objects) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            let moved = self.dir.join(OBJECTS_DIR).join(entry.file_name());
+            if moved.exists() {
+                fs::remove_file(entry.path())?;
+            } else {
+                fs::rename(entry.path(), moved)?;
+            }
+        }
+
+        fs::remove_dir(objects)
+    }
+}
@@ -0,0 +1,35 @@
+use bevy::prelude::*;
+
+// The stages that only need the chunk itself, so they can run on tasks
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GenStage {
+    Terrain,
+    Carve,
+    Features,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ChunkStage {
+    Generated(GenStage),
+    Light,
+    Ready,
+}
+
+impl ChunkStage {
+    pub fn next(self) -> Option<Self> {
+        match self {
+            Self::Generated(GenStage::Terrain) => Some(Self::Generated(GenStage::Carve)),
+            Self::Generated(GenStage::Carve) => Some(Self::Generated(GenStage::Features)),
+            Self::Generated(GenStage::Features) => Some(Self::Light),
+            Self::Light => Some(Self::Ready),
+            Self::Ready => None,
+        }
+    }
+}
+
+pub fn neighbors(pos: IVec3) -> impl Iterator<Item = IVec3> {
+    (-1..=1)
+        .flat_map(|x| (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z))))
+        .filter(|offset| *offset != IVec3::ZERO)
+        .map(move |offset| pos + offset)
+}
@@ -1,7 +1,41 @@
 use bevy::prelude::*;
 
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Material(pub u16);
+
+impl Material {
+    pub const SOLID: Self = Self(0);
+    pub const WATER: Self = Self(1);
+    pub const BUILT_IN: [Self; 2] = [Self::SOLID, Self::WATER];
+
+    pub fn is_liquid(self) -> bool {
+        self == Self::WATER
+    }
+
+    pub fn hides(self, other: Self) -> bool {
+        !self.is_liquid() || other.is_liquid()
+    }
+}
+
 #[derive(Clone)]
 pub struct Vox {
     pub color: Color,
+    pub material: Material,
     pub visible: bool,
+    pub light: f32,
+}
+
+impl Vox {
+    pub fn new(color: Color, material: Material) -> Self {
+        Self {
+            color,
+            material,
+            visible: true,
+            light: 1.,
+        }
+    }
+
+    pub fn solid(color: Color) -> Self {
+        Self::new(color, Material::SOLID)
+    }
 }
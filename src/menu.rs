@@ -1,15 +1,28 @@
-use bevy::prelude::*;
+use std::sync::Arc;
+
+use bevy::{prelude::*, window::ReceivedCharacter};
 use bevy_asset_loader::AssetCollection;
 
-use crate::state::{BufferedState, GameState, OpeningGame};
+use crate::{
+    game::{now, CurrentWorld, GeneratorName, WorldGenerators, WorldSave},
+    state::{BufferedState, GameState, OpeningGame, WorldError},
+};
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(init_main_menu))
+        app.init_resource::<LeaveMenus>()
+            .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(init_main_menu))
             .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(init_menu))
-            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(button_action))
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(button_action)
+                    .with_system(type_text)
+                    .with_system(rebuild_menu)
+                    .with_system(show_world_error),
+            )
+            .add_system_set(SystemSet::on_resume(GameState::Menu).with_system(refresh_menu))
             .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(term_menu));
     }
 }
@@ -20,11 +33,23 @@ pub struct Fonts {
     font: Handle<Font>,
 }
 
+type BuildMenu = Arc<dyn Fn(&WorldGenerators) -> Menu + Send + Sync>;
+
 #[derive(Clone, Component)]
 enum Action {
     Menu(Menu),
+    BuildMenu(BuildMenu),
     Back,
     Game,
+    CreateWorld(String),
+    LoadWorld(WorldSave),
+    RenameWorld(WorldSave),
+    DuplicateWorld(WorldSave),
+    DeleteWorld(WorldSave),
+    CreateSnapshot(WorldSave),
+    RestoreSnapshot(WorldSave, String),
+    DeleteSnapshot(WorldSave, String),
+    PruneSnapshots(WorldSave),
 }
 
 #[derive(Clone)]
@@ -43,9 +68,19 @@ struct MenuButton {
 struct Menu {
     title: String,
     title_size: MenuTitleSize,
+    message: Option<String>,
+    input: Option<String>,
     buttons: Vec<MenuButton>,
+    rebuild: Option<BuildMenu>,
 }
 
+// Stores the menu it belongs to, since menus further down the stack may have inputs too
+#[derive(Component)]
+struct TextInput(Entity);
+
+#[derive(Component, Deref)]
+struct RebuildMenu(BuildMenu);
+
 const MENU_ITEM_MARGIN: Rect<Val> = Rect {
     left: Val::Percent(0.),
     right: Val::Percent(0.),
@@ -64,73 +99,136 @@ const BUTTON_HOVER_COLOR: Color = Color::rgb(0.75, 0.75, 0.75);
 const BUTTON_PRESS_COLOR: Color = Color::GRAY;
 const BUTTON_TEXT_SIZE: f32 = 50.;
 const BUTTON_TEXT_COLOR: Color = Color::BLACK;
+const MESSAGE_SIZE: f32 = 30.;
+const MESSAGE_COLOR: Color = Color::WHITE;
+const INPUT_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const INPUT_TEXT_COLOR: Color = Color::WHITE;
+const MAX_INPUT_LEN: usize = 32;
+const DEFAULT_SNAPSHOT_NAME: &str = "Snapshot";
+const SNAPSHOT_MAX_AGE: u64 = 30 * 24 * 60 * 60;
+const SNAPSHOT_MAX_COUNT: usize = 10;
 
 impl Menu {
     fn spawn(&self, commands: &mut Commands, fonts: &Fonts) -> Entity {
-        commands
-            .spawn_bundle(NodeBundle {
+        let mut menu = commands.spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        });
+
+        if let Some(rebuild) = &self.rebuild {
+            menu.insert(RebuildMenu(rebuild.clone()));
+        }
+
+        let menu_e = menu.id();
+        menu.with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
                 style: Style {
-                    flex_direction: FlexDirection::ColumnReverse,
-                    align_items: AlignItems::Center,
-                    justify_content: JustifyContent::Center,
-                    size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                    margin: MENU_ITEM_MARGIN.clone(),
                     ..default()
                 },
-                color: Color::NONE.into(),
+                text: Text::with_section(
+                    self.title.clone(),
+                    TextStyle {
+                        font: fonts.font.clone(),
+                        font_size: match self.title_size {
+                            MenuTitleSize::MainTitle => MENU_TITLE_SIZE,
+                            MenuTitleSize::Heading => MENU_HEADING_SIZE,
+                        },
+                        color: MENU_TITLE_COLOR,
+                    },
+                    default(),
+                ),
                 ..default()
-            })
-            .with_children(|parent| {
+            });
+
+            if let Some(message) = &self.message {
                 parent.spawn_bundle(TextBundle {
                     style: Style {
                         margin: MENU_ITEM_MARGIN.clone(),
                         ..default()
                     },
                     text: Text::with_section(
-                        self.title.clone(),
+                        message.clone(),
                         TextStyle {
                             font: fonts.font.clone(),
-                            font_size: match self.title_size {
-                                MenuTitleSize::MainTitle => MENU_TITLE_SIZE,
-                                MenuTitleSize::Heading => MENU_HEADING_SIZE,
-                            },
-                            color: MENU_TITLE_COLOR,
+                            font_size: MESSAGE_SIZE,
+                            color: MESSAGE_COLOR,
                         },
                         default(),
                     ),
                     ..default()
                 });
+            }
 
-                for button in &self.buttons {
-                    parent
-                        .spawn_bundle(ButtonBundle {
-                            style: Style {
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                margin: MENU_ITEM_MARGIN.clone(),
-                                size: BUTTON_SIZE.clone(),
-                                ..default()
-                            },
-                            color: BUTTON_COLOR.into(),
+            if let Some(input) = &self.input {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            margin: MENU_ITEM_MARGIN.clone(),
+                            size: BUTTON_SIZE.clone(),
                             ..default()
-                        })
-                        .insert(button.action.clone())
-                        .with_children(|parent| {
-                            parent.spawn_bundle(TextBundle {
+                        },
+                        color: INPUT_COLOR.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle {
                                 text: Text::with_section(
-                                    button.text.clone(),
+                                    input.clone(),
                                     TextStyle {
                                         font: fonts.font.clone(),
                                         font_size: BUTTON_TEXT_SIZE,
-                                        color: BUTTON_TEXT_COLOR,
+                                        color: INPUT_TEXT_COLOR,
                                     },
                                     default(),
                                 ),
                                 ..default()
-                            });
+                            })
+                            .insert(TextInput(menu_e));
+                    });
+            }
+
+            for button in &self.buttons {
+                parent
+                    .spawn_bundle(ButtonBundle {
+                        style: Style {
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::Center,
+                            margin: MENU_ITEM_MARGIN.clone(),
+                            size: BUTTON_SIZE.clone(),
+                            ..default()
+                        },
+                        color: BUTTON_COLOR.into(),
+                        ..default()
+                    })
+                    .insert(button.action.clone())
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                button.text.clone(),
+                                TextStyle {
+                                    font: fonts.font.clone(),
+                                    font_size: BUTTON_TEXT_SIZE,
+                                    color: BUTTON_TEXT_COLOR,
+                                },
+                                default(),
+                            ),
+                            ..default()
                         });
-                }
-            })
-            .id()
+                    });
+            }
+        })
+        .id()
     }
 }
 
@@ -140,29 +238,164 @@ struct MenuEs(Vec<Entity>);
 #[derive(Deref)]
 struct NextMenu(Menu);
 
-fn init_main_menu(mut commands: Commands, mut state: ResMut<State<GameState>>) {
-    commands.spawn_bundle(UiCameraBundle::default());
+fn back_button() -> MenuButton {
+    MenuButton {
+        text: "Back".to_string(),
+        action: Action::Back,
+    }
+}
+
+fn generator_menu(generators: &WorldGenerators) -> Menu {
+    Menu {
+        title: "Choose a generator".to_string(),
+        title_size: MenuTitleSize::Heading,
+        message: None,
+        input: None,
+        buttons: generators
+            .names()
+            .map(|name| MenuButton {
+                text: name.to_string(),
+                action: Action::CreateWorld(name.to_string()),
+            })
+            .chain([back_button()])
+            .collect(),
+        rebuild: None,
+    }
+}
+
+fn world_list_menu(generators: &WorldGenerators) -> Menu {
+    Menu {
+        title: "Choose a world".to_string(),
+        title_size: MenuTitleSize::Heading,
+        message: None,
+        input: None,
+        buttons: [MenuButton {
+            text: "Create World".to_string(),
+            action: Action::Menu(generator_menu(generators)),
+        }]
+        .into_iter()
+        .chain(WorldSave::list().into_iter().map(|(save, meta)| {
+            let button = |text: &str, action: fn(WorldSave) -> Action| MenuButton {
+                text: text.to_string(),
+                action: action(save.clone()),
+            };
 
-    commands.insert_resource(NextMenu(Menu {
-        title: "voxmod".to_string(),
-        title_size: MenuTitleSize::MainTitle,
-        buttons: vec![
             MenuButton {
-                text: "Play".to_string(),
+                text: format!("{} ({})", meta.name, meta.generator),
                 action: Action::Menu(Menu {
-                    title: "Choose a world".to_string(),
+                    title: meta.name.clone(),
                     title_size: MenuTitleSize::Heading,
+                    message: None,
+                    input: Some(meta.name.clone()),
                     buttons: vec![
+                        button("Load", Action::LoadWorld),
+                        button("Rename", Action::RenameWorld),
                         MenuButton {
-                            text: "Create World".to_string(),
-                            action: Action::Game,
+                            text: "Snapshots".to_string(),
+                            action: Action::BuildMenu(snapshot_menu(save.clone())),
                         },
+                        button("Duplicate", Action::DuplicateWorld),
                         MenuButton {
-                            text: "Back".to_string(),
-                            action: Action::Back,
+                            text: "Delete".to_string(),
+                            action: Action::Menu(Menu {
+                                title: format!("Delete {}?", meta.name),
+                                title_size: MenuTitleSize::Heading,
+                                message: Some(
+                                    "Deleting removes the world and all its snapshots".to_string(),
+                                ),
+                                input: None,
+                                buttons: vec![button("Delete", Action::DeleteWorld), back_button()],
+                                rebuild: None,
+                            }),
                         },
+                        back_button(),
                     ],
+                    rebuild: None,
                 }),
+            }
+        }))
+        .chain([back_button()])
+        .collect(),
+        rebuild: Some(Arc::new(world_list_menu)),
+    }
+}
+
+fn age(time: u64) -> String {
+    match now().saturating_sub(time) {
+        age if age < 60 => "just now".to_string(),
+        age if age < 60 * 60 => format!("{}m ago", age / 60),
+        age if age < 24 * 60 * 60 => format!("{}h ago", age / (60 * 60)),
+        age => format!("{}d ago", age / (24 * 60 * 60)),
+    }
+}
+
+fn snapshot_menu(save: WorldSave) -> BuildMenu {
+    Arc::new(move |_| Menu {
+        title: "Snapshots".to_string(),
+        title_size: MenuTitleSize::Heading,
+        message: None,
+        input: Some(DEFAULT_SNAPSHOT_NAME.to_string()),
+        buttons: [MenuButton {
+            text: "Create Snapshot".to_string(),
+            action: Action::CreateSnapshot(save.clone()),
+        }]
+        .into_iter()
+        .chain(save.snapshots().into_iter().map(|snapshot| MenuButton {
+            text: format!("{} ({})", snapshot.name, age(snapshot.created)),
+            action: Action::Menu(Menu {
+                title: snapshot.name,
+                title_size: MenuTitleSize::Heading,
+                message: Some("Restoring replaces the world with this snapshot".to_string()),
+                input: None,
+                buttons: vec![
+                    MenuButton {
+                        text: "Restore".to_string(),
+                        action: Action::RestoreSnapshot(save.clone(), snapshot.id.clone()),
+                    },
+                    MenuButton {
+                        text: "Delete".to_string(),
+                        action: Action::DeleteSnapshot(save.clone(), snapshot.id),
+                    },
+                    back_button(),
+                ],
+                rebuild: None,
+            }),
+        }))
+        .chain([
+            MenuButton {
+                text: "Prune Old Snapshots".to_string(),
+                action: Action::PruneSnapshots(save.clone()),
+            },
+            back_button(),
+        ])
+        .collect(),
+        rebuild: Some(snapshot_menu(save.clone())),
+    })
+}
+
+fn error_menu(message: String) -> Menu {
+    Menu {
+        title: "Can't open world".to_string(),
+        title_size: MenuTitleSize::Heading,
+        message: Some(message),
+        input: None,
+        buttons: vec![back_button()],
+        rebuild: None,
+    }
+}
+
+fn init_main_menu(mut commands: Commands, mut state: ResMut<State<GameState>>) {
+    commands.spawn_bundle(UiCameraBundle::default());
+
+    commands.insert_resource(NextMenu(Menu {
+        title: "voxmod".to_string(),
+        title_size: MenuTitleSize::MainTitle,
+        message: None,
+        input: None,
+        buttons: vec![
+            MenuButton {
+                text: "Play".to_string(),
+                action: Action::BuildMenu(Arc::new(world_list_menu)),
             },
             MenuButton {
                 text: "Edit".to_string(),
@@ -173,6 +406,7 @@ fn init_main_menu(mut commands: Commands, mut state: ResMut<State<GameState>>) {
                 action: Action::Back,
             },
         ],
+        rebuild: None,
     }));
     state.push(GameState::Menu).unwrap();
 }
@@ -195,28 +429,146 @@ fn init_menu(
     commands.remove_resource::<NextMenu>();
 }
 
+struct RefreshMenu;
+
+// How many more menus to leave as they resume, to get back past menus about something that's gone
+#[derive(Default, Deref, DerefMut)]
+struct LeaveMenus(usize);
+
+fn input_text(inputs: &Query<(&Text, &TextInput)>, menu_es: &MenuEs) -> String {
+    inputs
+        .iter()
+        .find(|(_, input)| Some(&input.0) == menu_es.last())
+        .map_or_else(default, |(text, _)| {
+            text.sections[0].value.trim().to_string()
+        })
+}
+
+fn push_menu(commands: &mut Commands, state: &mut State<GameState>, menu: Menu) {
+    commands.insert_resource(BufferedState(GameState::Menu));
+    commands.insert_resource(NextMenu(menu));
+    state.push(GameState::Buffer).unwrap();
+}
+
+fn open_world(
+    commands: &mut Commands,
+    state: &mut State<GameState>,
+    generators: &WorldGenerators,
+    save: &WorldSave,
+) {
+    match save.open() {
+        Ok(meta) if generators.names().any(|name| name == meta.generator) => {
+            commands.insert_resource(CurrentWorld {
+                save: save.clone(),
+                meta,
+            });
+            commands.insert_resource(OpeningGame);
+            state.replace(GameState::Game).unwrap()
+        }
+        Ok(meta) => push_menu(
+            commands,
+            state,
+            error_menu(format!("No world generator named \"{}\"", meta.generator)),
+        ),
+        Err(err) => {
+            warn!("Failed to open world: {}", err);
+            push_menu(commands, state, error_menu(err.to_string()))
+        }
+    }
+}
+
 fn button_action(
     mut commands: Commands,
     mut interactions: Query<
         (&Interaction, &mut UiColor, &Action),
         (Changed<Interaction>, With<Button>),
     >,
+    inputs: Query<(&Text, &TextInput)>,
+    menu_es: Res<MenuEs>,
+    generators: Res<WorldGenerators>,
+    mut leave_menus: ResMut<LeaveMenus>,
     mut state: ResMut<State<GameState>>,
 ) {
     for (interaction, mut color, action) in interactions.iter_mut() {
         *color = match interaction {
             Interaction::Clicked => {
                 match action {
-                    Action::Menu(menu) => {
-                        commands.insert_resource(BufferedState(GameState::Menu));
-                        commands.insert_resource(NextMenu(menu.clone()));
-                        state.push(GameState::Buffer).unwrap();
+                    Action::Menu(menu) => push_menu(&mut commands, &mut state, menu.clone()),
+                    Action::BuildMenu(build) => {
+                        push_menu(&mut commands, &mut state, build(&generators))
                     }
                     Action::Back => state.pop().unwrap(),
-                    Action::Game => {
+                    // Picks up the last played world, only creating one when there are none
+                    Action::Game => match WorldSave::list().into_iter().next() {
+                        Some((save, _)) => {
+                            open_world(&mut commands, &mut state, &generators, &save)
+                        }
+                        None => {
+                            commands.insert_resource(OpeningGame);
+                            state.replace(GameState::Game).unwrap()
+                        }
+                    },
+                    Action::CreateWorld(generator) => {
+                        commands.insert_resource(GeneratorName(generator.clone()));
                         commands.insert_resource(OpeningGame);
                         state.replace(GameState::Game).unwrap()
                     }
+                    Action::LoadWorld(save) => {
+                        open_world(&mut commands, &mut state, &generators, save)
+                    }
+                    Action::RenameWorld(save) => {
+                        let name = input_text(&inputs, &menu_es);
+                        if !name.is_empty() {
+                            if let Err(err) = save.rename(&name) {
+                                warn!("Failed to rename world: {}", err);
+                            }
+                            state.pop().unwrap();
+                        }
+                    }
+                    Action::DuplicateWorld(save) => {
+                        if let Err(err) = save.duplicate() {
+                            warn!("Failed to duplicate world: {}", err);
+                        }
+                        state.pop().unwrap();
+                    }
+                    // Back to the world list, past the deleted world's menu
+                    Action::DeleteWorld(save) => {
+                        if let Err(err) = save.delete() {
+                            warn!("Failed to delete world: {}", err);
+                        }
+                        **leave_menus += 1;
+                        state.pop().unwrap();
+                    }
+                    Action::CreateSnapshot(save) => {
+                        let name = match input_text(&inputs, &menu_es) {
+                            name if name.is_empty() => DEFAULT_SNAPSHOT_NAME.to_string(),
+                            name => name,
+                        };
+                        if let Err(err) = save.create_snapshot(&name) {
+                            warn!("Failed to create snapshot: {}", err);
+                        }
+                        commands.insert_resource(RefreshMenu);
+                    }
+                    Action::RestoreSnapshot(save, id) => {
+                        if let Err(err) = save.restore_snapshot(id) {
+                            warn!("Failed to restore snapshot: {}", err);
+                        }
+                        state.pop().unwrap();
+                    }
+                    Action::DeleteSnapshot(save, id) => {
+                        if let Err(err) = save.delete_snapshot(id) {
+                            warn!("Failed to delete snapshot: {}", err);
+                        }
+                        state.pop().unwrap();
+                    }
+                    Action::PruneSnapshots(save) => {
+                        match save.prune_snapshots(Some(SNAPSHOT_MAX_AGE), Some(SNAPSHOT_MAX_COUNT))
+                        {
+                            Ok(count) => info!("Pruned {} snapshots", count),
+                            Err(err) => warn!("Failed to prune snapshots: {}", err),
+                        }
+                        commands.insert_resource(RefreshMenu);
+                    }
                 }
                 BUTTON_PRESS_COLOR
             }
@@ -227,6 +579,75 @@ fn button_action(
     }
 }
 
+fn show_world_error(
+    mut commands: Commands,
+    error: Option<Res<WorldError>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if let Some(error) = error {
+        commands.remove_resource::<WorldError>();
+        push_menu(&mut commands, &mut state, error_menu(error.0.clone()));
+    }
+}
+
+fn type_text(
+    mut chars: EventReader<ReceivedCharacter>,
+    mut inputs: Query<(&mut Text, &TextInput)>,
+    menu_es: Res<MenuEs>,
+) {
+    for (mut text, input) in inputs.iter_mut() {
+        if Some(&input.0) != menu_es.last() {
+            continue;
+        }
+
+        let value = &mut text.sections[0].value;
+        for char in chars.iter() {
+            match char.char {
+                '\u{8}' => {
+                    value.pop();
+                }
+                char if !char.is_control() && value.chars().count() < MAX_INPUT_LEN => {
+                    value.push(char)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn refresh_menu(
+    mut commands: Commands,
+    mut leave_menus: ResMut<LeaveMenus>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if **leave_menus > 0 {
+        **leave_menus -= 1;
+        state.pop().unwrap();
+    } else {
+        commands.insert_resource(RefreshMenu);
+    }
+}
+
+fn rebuild_menu(
+    mut commands: Commands,
+    refresh: Option<Res<RefreshMenu>>,
+    mut menu_es: ResMut<MenuEs>,
+    rebuilds: Query<&RebuildMenu>,
+    fonts: Res<Fonts>,
+    generators: Res<WorldGenerators>,
+) {
+    if refresh.is_none() {
+        return;
+    }
+
+    commands.remove_resource::<RefreshMenu>();
+    let menu_e = menu_es.last_mut().unwrap();
+    if let Ok(rebuild) = rebuilds.get(*menu_e) {
+        commands.entity(*menu_e).despawn_recursive();
+        *menu_e = rebuild(&generators).spawn(&mut commands, &fonts);
+    }
+}
+
 fn term_menu(
     mut commands: Commands,
     mut nodes: Query<&mut Style, With<Node>>,
@@ -24,6 +24,9 @@ pub struct BufferedState(pub GameState);
 
 pub struct OpeningGame;
 
+// Why the game went back to the menu, shown once the menu opens
+pub struct WorldError(pub String);
+
 fn push_state(
     mut commands: Commands,
     buffered_state: Res<BufferedState>,